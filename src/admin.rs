@@ -0,0 +1,360 @@
+/// A framework for implementing Janus admin API requests, i.e. the `handle_admin_message` plugin callback, as a set of
+/// named commands with typed requests and responses.
+use crate::jansson::{JanssonValue, RawJanssonValue};
+use crate::session::{QuerySession, SessionRegistry, SessionWrapper};
+use crate::PluginCallbacks;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ptr;
+use std::sync::Arc;
+use std::time::Instant;
+
+type Handler = Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync>;
+type RawHandler = Box<dyn Fn(&JanssonValue) -> Option<JanssonValue> + Send + Sync>;
+
+enum Command {
+    Help,
+    Typed(Handler),
+    Raw(RawHandler),
+}
+
+struct Entry {
+    description: String,
+    command: Command,
+}
+
+#[derive(Debug, Deserialize)]
+struct AdminRequest {
+    request: String,
+}
+
+#[derive(Debug, Serialize)]
+struct CommandDescription<'a> {
+    name: &'a str,
+    description: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct HelpRequest {
+    command: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KickRequest {
+    id: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct SessionDescription<S> {
+    id: u64,
+    idle_secs: f64,
+    state: S,
+}
+
+#[derive(Debug, Serialize)]
+struct Stats {
+    sessions: usize,
+    uptime_secs: u64,
+}
+
+/// A set of named admin API commands. A request like `{"request": "kick_session", "id": 1234}` is dispatched to the
+/// command named by its `request` field, which receives the whole request deserialized into its own request type.
+/// Whatever it returns is sent back with `"result": "ok"` added, and any error is sent back as
+/// `{"result": "error", "error": "..."}`, like the rest of the admin API.
+///
+/// Every registry starts out with `list_commands` and `help` commands, which describe the registered commands.
+pub struct AdminRegistry {
+    commands: BTreeMap<String, Entry>,
+}
+
+impl AdminRegistry {
+    /// Creates a registry containing only the built-in `list_commands` and `help` commands.
+    pub fn new() -> Self {
+        let mut commands = BTreeMap::new();
+        for name in &["help", "list_commands"] {
+            let description = "Lists the available commands, or describes the one named by \"command\".";
+            commands.insert((*name).to_owned(), Entry { description: description.to_owned(), command: Command::Help });
+        }
+        Self { commands }
+    }
+
+    /// Registers a command. The handler receives the request deserialized as a `Req`, and should return a `Resp`
+    /// which serializes to a JSON object, or an error message. Replaces any existing command with the same name.
+    pub fn register<Req, Resp, F>(&mut self, name: &str, description: &str, handler: F) -> &mut Self
+    where
+        Req: DeserializeOwned,
+        Resp: Serialize,
+        F: Fn(Req) -> Result<Resp, String> + Send + Sync + 'static,
+    {
+        let handler = move |request: Value| {
+            let request = serde_json::from_value(request).map_err(|e| format!("Invalid request: {}", e))?;
+            let response = handler(request)?;
+            serde_json::to_value(response).map_err(|e| format!("Error serializing response: {}", e))
+        };
+        self.insert(name, description, Command::Typed(Box::new(handler)))
+    }
+
+    /// Registers a command which handles the raw request itself and returns the whole response, or None if it doesn't
+    /// handle the request after all. Commands for `ConfigReloader`, `MetricsRegistry` and `PersistentState` are
+    /// registered by their own `register_admin_command` methods instead.
+    pub fn register_raw<F>(&mut self, name: &str, description: &str, handler: F) -> &mut Self
+    where
+        F: Fn(&JanssonValue) -> Option<JanssonValue> + Send + Sync + 'static,
+    {
+        self.insert(name, description, Command::Raw(Box::new(handler)))
+    }
+
+    /// Registers the standard session commands over the given registry:
+    ///
+    /// - `list_sessions`, which lists every session's ID, idle time and `query_session` snapshot;
+    /// - `kick_session`, which asks Janus to end the session with the given `"id"`;
+    /// - `stats`, which reports the number of sessions and how long ago the commands were registered.
+    ///
+    /// Sessions are identified by the IDs the registry assigned them.
+    pub fn register_sessions<T>(&mut self, registry: Arc<SessionRegistry<T>>, callbacks: &'static PluginCallbacks) -> &mut Self
+    where
+        T: QuerySession + Send + Sync + 'static,
+    {
+        let sessions = Arc::clone(&registry);
+        self.register("list_sessions", "Lists the open sessions.", move |_: Value| {
+            let sessions = sessions.sessions_by_id().iter().map(|(id, s)| describe_session(*id, s)).collect::<Vec<_>>();
+            Ok(serde_json::json!({ "sessions": sessions }))
+        });
+        let sessions = Arc::clone(&registry);
+        self.register("kick_session", "Ends the session with the given \"id\".", move |req: KickRequest| {
+            match sessions.get_by_id(req.id) {
+                Some(session) => {
+                    (callbacks.end_session)(session.as_ptr());
+                    Ok(serde_json::json!({}))
+                }
+                None => Err(format!("No such session: {}", req.id)),
+            }
+        });
+        let started = Instant::now();
+        self.register("stats", "Reports statistics about the plugin.", move |_: Value| {
+            Ok(Stats { sessions: registry.len(), uptime_secs: started.elapsed().as_secs() })
+        })
+    }
+
+    fn insert(&mut self, name: &str, description: &str, command: Command) -> &mut Self {
+        self.commands.insert(name.to_owned(), Entry { description: description.to_owned(), command });
+        self
+    }
+
+    /// Handles an admin API request, returning the response to send back.
+    pub fn dispatch(&self, message: &JanssonValue) -> JanssonValue {
+        match message.to_serde::<Value>() {
+            Ok(request) => self.dispatch_value(message, request),
+            Err(e) => to_jansson(error_response(format!("Invalid request: {}", e))),
+        }
+    }
+
+    fn dispatch_value(&self, message: &JanssonValue, request: Value) -> JanssonValue {
+        let name = match AdminRequest::deserialize(&request) {
+            Ok(req) => req.request,
+            Err(_) => return to_jansson(error_response("Missing \"request\" field.".to_owned())),
+        };
+        let entry = match self.commands.get(&name) {
+            Some(entry) => entry,
+            None => return to_jansson(error_response(format!("Unknown request \"{}\"; try \"list_commands\".", name))),
+        };
+        let result = match entry.command {
+            Command::Raw(ref handler) => {
+                return handler(message).unwrap_or_else(|| to_jansson(error_response(format!("Request \"{}\" wasn't handled.", name))));
+            }
+            Command::Help => self.help(request),
+            Command::Typed(ref handler) => handler(request),
+        };
+        to_jansson(match result {
+            Ok(response) => ok_response(response),
+            Err(e) => error_response(e),
+        })
+    }
+
+    fn help(&self, request: Value) -> Result<Value, String> {
+        match HelpRequest::deserialize(&request).ok().and_then(|r| r.command) {
+            Some(command) => match self.commands.get_key_value(&command) {
+                Some(entry) => Ok(serde_json::json!(describe_command(entry))),
+                None => Err(format!("Unknown command \"{}\".", command)),
+            },
+            None => Ok(serde_json::json!({ "commands": self.commands.iter().map(describe_command).collect::<Vec<_>>() })),
+        }
+    }
+
+    /// Handles an admin API request passed to the `handle_admin_message` plugin callback, returning the response as
+    /// a new reference for Janus to send back. Usually called via the `handle_admin_message_callback` macro.
+    ///
+    /// # Safety
+    ///
+    /// The message must be null or a valid Jansson value, which is borrowed rather than consumed.
+    pub unsafe fn dispatch_raw(&self, message: *mut RawJanssonValue) -> *mut RawJanssonValue {
+        match JanssonValue::from_and_incref(message) {
+            Some(message) => self.dispatch(&message).into_raw(),
+            None => ptr::null_mut(),
+        }
+    }
+}
+
+impl Default for AdminRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for AdminRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.commands.keys()).finish()
+    }
+}
+
+fn describe_command<'a>((name, entry): (&'a String, &'a Entry)) -> CommandDescription<'a> {
+    CommandDescription { name, description: &entry.description }
+}
+
+fn describe_session<T: QuerySession>(id: u64, session: &Arc<SessionWrapper<T>>) -> SessionDescription<T::Snapshot> {
+    SessionDescription { id, idle_secs: session.idle_for().as_secs_f64(), state: session.snapshot() }
+}
+
+fn ok_response(response: Value) -> Value {
+    let mut body = serde_json::Map::new();
+    body.insert("result".to_owned(), Value::from("ok"));
+    match response {
+        Value::Object(fields) => body.extend(fields),
+        Value::Null => {}
+        other => {
+            body.insert("response".to_owned(), other);
+        }
+    }
+    Value::Object(body)
+}
+
+fn error_response(error: String) -> Value {
+    serde_json::json!({ "result": "error", "error": error })
+}
+
+fn to_jansson(response: Value) -> JanssonValue {
+    JanssonValue::from_serde(&response).expect("Error serializing admin response :(")
+}
+
+/// Defines a `handle_admin_message` plugin callback which dispatches requests to the given `AdminRegistry`. Pass
+/// `handle_admin_message` to `build_plugin!` to use it.
+#[macro_export]
+macro_rules! handle_admin_message_callback {
+    ($registry:expr) => {
+        extern "C" fn handle_admin_message(message: *mut $crate::RawJanssonValue) -> *mut $crate::RawJanssonValue {
+            unsafe { $registry.dispatch_raw(message) }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct EchoRequest {
+        text: String,
+    }
+
+    #[derive(Serialize)]
+    struct EchoResponse {
+        echo: String,
+    }
+
+    fn request(json: Value) -> Value {
+        let registry = example_registry();
+        registry.dispatch(&JanssonValue::from_serde(&json).unwrap()).to_serde().unwrap()
+    }
+
+    fn example_registry() -> AdminRegistry {
+        let mut registry = AdminRegistry::new();
+        registry.register("echo", "Echoes \"text\".", |req: EchoRequest| {
+            if req.text.is_empty() {
+                Err("Nothing to echo.".to_owned())
+            } else {
+                Ok(EchoResponse { echo: req.text })
+            }
+        });
+        registry.register_raw("ping", "Responds with \"pong\".", |_| {
+            Some(JanssonValue::from_serde(&serde_json::json!({ "result": "pong" })).unwrap())
+        });
+        Arc::new(crate::metrics::MetricsRegistry::new()).register_admin_command(&mut registry);
+        registry
+    }
+
+    #[test]
+    fn dispatch_typed_commands() {
+        assert_eq!(request(serde_json::json!({ "request": "echo", "text": "hi" })), serde_json::json!({ "result": "ok", "echo": "hi" }));
+        assert_eq!(request(serde_json::json!({ "request": "echo", "text": "" })), serde_json::json!({ "result": "error", "error": "Nothing to echo." }));
+        assert_eq!(request(serde_json::json!({ "request": "echo" }))["result"], "error");
+        assert_eq!(request(serde_json::json!({ "request": "ping" })), serde_json::json!({ "result": "pong" }));
+        assert_eq!(request(serde_json::json!({ "request": "metrics" })), serde_json::json!({ "result": "ok", "metrics": [] }));
+        assert_eq!(request(serde_json::json!({ "request": "nope" }))["result"], "error");
+        assert_eq!(request(serde_json::json!({ "text": "hi" }))["result"], "error");
+    }
+
+    #[test]
+    fn describe_commands() {
+        let response = request(serde_json::json!({ "request": "list_commands" }));
+        let names: Vec<_> = response["commands"].as_array().unwrap().iter().map(|c| c["name"].as_str().unwrap().to_owned()).collect();
+        assert_eq!(names, vec!["echo", "help", "list_commands", "metrics", "ping"]);
+        assert_eq!(
+            request(serde_json::json!({ "request": "help", "command": "echo" })),
+            serde_json::json!({ "result": "ok", "name": "echo", "description": "Echoes \"text\"." })
+        );
+    }
+
+    #[test]
+    fn session_commands() {
+        use crate::janus_stubs;
+        use crate::session::test_handle;
+        use crate::PluginSession;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static ENDED: AtomicUsize = AtomicUsize::new(0);
+        extern "C" fn end_session(handle: *mut PluginSession) {
+            ENDED.store(handle as usize, Ordering::SeqCst);
+        }
+        struct State(u32);
+        impl QuerySession for State {
+            type Snapshot = u32;
+            fn snapshot(&self) -> u32 {
+                self.0
+            }
+        }
+
+        let callbacks = Box::leak(Box::new(PluginCallbacks { end_session, ..janus_stubs::callbacks() }));
+        let sessions = Arc::new(SessionRegistry::new());
+        let (mut first, mut second) = (test_handle(), test_handle());
+        let first_session = unsafe { SessionWrapper::associate(&mut first, State(1)).unwrap() };
+        let second_session = unsafe { SessionWrapper::associate(&mut second, State(2)).unwrap() };
+        sessions.insert(Arc::clone(&first_session));
+        sessions.insert(Arc::clone(&second_session));
+        let mut registry = AdminRegistry::new();
+        registry.register_sessions(Arc::clone(&sessions), callbacks);
+        let request = |json: Value| registry.dispatch(&JanssonValue::from_serde(&json).unwrap()).to_serde::<Value>().unwrap();
+
+        let listed = request(serde_json::json!({ "request": "list_sessions" }));
+        let listed = listed["sessions"].as_array().unwrap();
+        let ids_and_states = listed.iter().map(|s| (s["id"].clone(), s["state"].clone())).collect::<Vec<_>>();
+        assert_eq!(ids_and_states, vec![(1.into(), 1.into()), (2.into(), 2.into())]);
+
+        assert_eq!(request(serde_json::json!({ "request": "kick_session", "id": 2 })), serde_json::json!({ "result": "ok" }));
+        assert_eq!(ENDED.load(Ordering::SeqCst), &mut second as *mut _ as usize);
+        let unknown = &mut first as *mut PluginSession as usize;
+        let kicked = request(serde_json::json!({ "request": "kick_session", "id": unknown }));
+        assert_eq!(kicked, serde_json::json!({ "result": "error", "error": format!("No such session: {}", unknown) }));
+        assert_eq!(request(serde_json::json!({ "request": "kick_session", "id": 3 }))["result"], "error");
+        assert_eq!(ENDED.load(Ordering::SeqCst), &mut second as *mut _ as usize);
+
+        sessions.remove(&mut second);
+        let stats = request(serde_json::json!({ "request": "stats" }));
+        assert_eq!((stats["result"].as_str(), stats["sessions"].as_u64()), (Some("ok"), Some(1)));
+        assert_eq!(stats["uptime_secs"], 0);
+        sessions.clear();
+    }
+}
@@ -0,0 +1,158 @@
+/// Utilities to check Janus signed tokens presented by clients in their signalling messages.
+use crate::jansson::JanssonValue;
+use crate::{Plugin, PluginCallbacks, PluginResult};
+use serde::Deserialize;
+use std::error::Error;
+use std::ffi::{CStr, CString};
+use std::fmt;
+
+/// The error text sent back to clients whose requests aren't authorized.
+fn unauthorized_text() -> &'static CStr {
+    CStr::from_bytes_with_nul(b"Unauthorized request (wrong or missing secret/token)\0").unwrap()
+}
+
+/// An error indicating why a client's request wasn't authorized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AuthError {
+    /// The request didn't contain a token.
+    MissingToken,
+    /// The token wasn't correctly signed, or has expired.
+    InvalidToken,
+    /// The token was valid, but doesn't grant access to the requested descriptor.
+    NotPermitted,
+}
+
+impl Error for AuthError {}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            AuthError::MissingToken => "No token was provided.",
+            AuthError::InvalidToken => "The provided token is invalid.",
+            AuthError::NotPermitted => "The provided token doesn't grant access to this resource.",
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenBearer {
+    token: Option<String>,
+}
+
+/// Validates signed tokens using Janus's token authentication machinery. Tokens are signed by the application
+/// with a list of descriptors (e.g. `room:1234`) which they grant access to.
+#[derive(Debug, Clone, Copy)]
+pub struct Auth {
+    callbacks: &'static PluginCallbacks,
+    plugin: &'static Plugin,
+}
+
+impl Auth {
+    /// Creates a validator for the given plugin, using the callbacks which Janus passed to `init`.
+    pub fn new(callbacks: &'static PluginCallbacks, plugin: &'static Plugin) -> Self {
+        Self { callbacks, plugin }
+    }
+
+    fn plugin_ptr(&self) -> *mut Plugin {
+        self.plugin as *const Plugin as *mut Plugin
+    }
+
+    /// Returns whether the token is correctly signed for this plugin and not expired.
+    pub fn is_signature_valid(&self, token: &CStr) -> bool {
+        (self.callbacks.auth_is_signature_valid)(self.plugin_ptr(), token.as_ptr()) != 0
+    }
+
+    /// Returns whether the token is valid and grants access to the given descriptor.
+    pub fn signature_contains(&self, token: &CStr, descriptor: &CStr) -> bool {
+        (self.callbacks.auth_signature_contains)(self.plugin_ptr(), token.as_ptr(), descriptor.as_ptr()) != 0
+    }
+
+    /// Checks that the token is valid and grants access to the given descriptor.
+    pub fn authorize(&self, token: Option<&str>, descriptor: &str) -> Result<(), AuthError> {
+        let token = token.ok_or(AuthError::MissingToken)?;
+        let token = CString::new(token).map_err(|_| AuthError::InvalidToken)?;
+        if !self.is_signature_valid(&token) {
+            return Err(AuthError::InvalidToken);
+        }
+        let descriptor = CString::new(descriptor).map_err(|_| AuthError::NotPermitted)?;
+        if !self.signature_contains(&token, &descriptor) {
+            return Err(AuthError::NotPermitted);
+        }
+        Ok(())
+    }
+
+    /// Checks that the `token` field of an incoming message is valid and grants access to the given descriptor.
+    pub fn authorize_message(&self, message: &JanssonValue, descriptor: &str) -> Result<(), AuthError> {
+        let bearer = message.to_serde::<TokenBearer>().map_err(|_| AuthError::MissingToken)?;
+        self.authorize(bearer.token.as_deref(), descriptor)
+    }
+
+    /// Guards a request in `handle_message`: checks the message's token like `authorize_message`, and if it's not
+    /// authorized, returns the standard unauthorized error result for the plugin to hand back to Janus.
+    pub fn guard(&self, message: &JanssonValue, descriptor: &str) -> Result<(), PluginResult> {
+        self.authorize_message(message, descriptor).map_err(|e| {
+            crate::janus_verb!("Rejecting unauthorized request for {}: {}", descriptor, e);
+            PluginResult::error(unauthorized_text())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::jansson::JanssonDecodingFlags;
+    use crate::janus_stubs;
+    use crate::PluginResultType;
+    use glib_sys::gboolean;
+    use std::os::raw::c_char;
+
+    // the stub signs tokens like "signed:room:1,room:2", granting access to the listed descriptors
+    fn granted(token: *const c_char) -> Option<Vec<String>> {
+        let token = unsafe { CStr::from_ptr(token) }.to_str().unwrap();
+        token.strip_prefix("signed:").map(|t| t.split(',').map(str::to_owned).collect())
+    }
+
+    extern "C" fn auth_is_signature_valid(_plugin: *mut Plugin, token: *const c_char) -> gboolean {
+        granted(token).is_some() as gboolean
+    }
+
+    extern "C" fn auth_signature_contains(_plugin: *mut Plugin, token: *const c_char, descriptor: *const c_char) -> gboolean {
+        let descriptor = unsafe { CStr::from_ptr(descriptor) }.to_str().unwrap();
+        granted(token).unwrap_or_default().iter().any(|d| d == descriptor) as gboolean
+    }
+
+    fn auth() -> Auth {
+        let callbacks = PluginCallbacks { auth_is_signature_valid, auth_signature_contains, ..janus_stubs::callbacks() };
+        Auth::new(Box::leak(Box::new(callbacks)), &janus_stubs::PLUGIN)
+    }
+
+    fn message(json: &str) -> JanssonValue {
+        JanssonValue::from_str(json, JanssonDecodingFlags::empty()).unwrap()
+    }
+
+    #[test]
+    fn authorize_tokens() {
+        let auth = auth();
+        assert_eq!(auth.authorize(None, "room:1"), Err(AuthError::MissingToken));
+        assert_eq!(auth.authorize(Some("forged:room:1"), "room:1"), Err(AuthError::InvalidToken));
+        assert_eq!(auth.authorize(Some("signed:\0room:1"), "room:1"), Err(AuthError::InvalidToken));
+        assert_eq!(auth.authorize(Some("signed:room:1,room:2"), "room:3"), Err(AuthError::NotPermitted));
+        assert_eq!(auth.authorize(Some("signed:room:1,room:2"), "room:2"), Ok(()));
+
+        assert_eq!(auth.authorize_message(&message(r#"{"request": "join"}"#), "room:1"), Err(AuthError::MissingToken));
+        assert_eq!(auth.authorize_message(&message(r#"{"token": 42}"#), "room:1"), Err(AuthError::MissingToken));
+        assert_eq!(auth.authorize_message(&message(r#"{"token": "signed:room:1"}"#), "room:2"), Err(AuthError::NotPermitted));
+        assert_eq!(auth.authorize_message(&message(r#"{"token": "signed:room:1"}"#), "room:1"), Ok(()));
+    }
+
+    #[test]
+    fn guard_requests() {
+        let auth = auth();
+        assert!(auth.guard(&message(r#"{"token": "signed:room:1"}"#), "room:1").is_ok());
+        let result = auth.guard(&message(r#"{"token": "forged:room:1"}"#), "room:1").unwrap_err();
+        assert_eq!(result.type_, PluginResultType::JANUS_PLUGIN_ERROR);
+        assert_eq!(unsafe { CStr::from_ptr(result.text) }, unauthorized_text());
+        assert!(result.content.is_null());
+    }
+}
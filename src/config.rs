@@ -0,0 +1,1020 @@
+/// Utilities to load a plugin's configuration file, in either Janus's libconfig-style `.jcfg` format or
+/// the legacy INI-style `.cfg` format, and deserialize it into a user-defined type.
+use crate::admin::AdminRegistry;
+use serde::de::{self, DeserializeOwned, Deserializer, IntoDeserializer, Visitor};
+use serde::Serialize;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// A location in a configuration file. Lines and columns are numbered from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// An error indicating that we failed to read, parse or deserialize a configuration file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    pub message: String,
+    pub position: Option<Position>,
+    pub path: Option<PathBuf>,
+}
+
+impl ConfigError {
+    fn at(position: Position, message: impl Into<String>) -> Self {
+        Self { message: message.into(), position: Some(position), path: None }
+    }
+
+    fn in_file(mut self, path: &Path) -> Self {
+        self.path = Some(path.to_owned());
+        self
+    }
+}
+
+impl Error for ConfigError {}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref path) = self.path {
+            write!(f, "{}: ", path.display())?;
+        }
+        if let Some(position) = self.position {
+            write!(f, "{}: ", position)?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+impl de::Error for ConfigError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self { message: msg.to_string(), position: None, path: None }
+    }
+}
+
+/// The contents of a configuration setting.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    /// A libconfig array or list.
+    List(Vec<ConfigValue>),
+    /// A libconfig group or an INI section, with settings in file order.
+    Group(Vec<(String, ConfigValue)>),
+}
+
+/// A configuration setting, along with where it was found in the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigValue {
+    pub value: Value,
+    pub position: Position,
+}
+
+impl ConfigValue {
+    /// If this is a group, returns the setting with the given name in it.
+    pub fn get(&self, name: &str) -> Option<&ConfigValue> {
+        match self.value {
+            Value::Group(ref settings) => settings.iter().find(|(n, _)| n == name).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn unexpected(&self) -> de::Unexpected<'_> {
+        match self.value {
+            Value::Bool(b) => de::Unexpected::Bool(b),
+            Value::Int(i) => de::Unexpected::Signed(i),
+            Value::Float(f) => de::Unexpected::Float(f),
+            Value::Str(ref s) => de::Unexpected::Str(s),
+            Value::List(_) => de::Unexpected::Seq,
+            Value::Group(_) => de::Unexpected::Map,
+        }
+    }
+
+    fn locate(&self, err: ConfigError) -> ConfigError {
+        match err.position {
+            Some(_) => err,
+            None => ConfigError { position: Some(self.position), ..err },
+        }
+    }
+}
+
+/// Scans a configuration file one character at a time, tracking the current position.
+struct Scanner {
+    chars: Vec<char>,
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Scanner {
+    fn new(input: &str) -> Self {
+        Self { chars: input.chars().collect(), offset: 0, line: 1, column: 1 }
+    }
+
+    fn position(&self) -> Position {
+        Position { line: self.line, column: self.column }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.offset).copied()
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        self.chars.get(self.offset + 1).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.offset += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, message: impl Into<String>) -> ConfigError {
+        ConfigError::at(self.position(), message)
+    }
+}
+
+/// Parses the contents of a libconfig-style `.jcfg` file into a group of settings.
+pub fn parse_jcfg(input: &str) -> Result<ConfigValue, ConfigError> {
+    let mut scanner = Scanner::new(input);
+    let position = scanner.position();
+    let settings = parse_settings(&mut scanner, None)?;
+    Ok(ConfigValue { value: Value::Group(settings), position })
+}
+
+fn skip_whitespace(s: &mut Scanner) -> Result<(), ConfigError> {
+    loop {
+        match (s.peek(), s.peek_second()) {
+            (Some(c), _) if c.is_whitespace() => {
+                s.bump();
+            }
+            (Some('#'), _) | (Some('/'), Some('/')) => {
+                while matches!(s.peek(), Some(c) if c != '\n') {
+                    s.bump();
+                }
+            }
+            (Some('/'), Some('*')) => {
+                let start = s.position();
+                s.bump();
+                s.bump();
+                loop {
+                    match (s.peek(), s.peek_second()) {
+                        (Some('*'), Some('/')) => {
+                            s.bump();
+                            s.bump();
+                            break;
+                        }
+                        (Some(_), _) => {
+                            s.bump();
+                        }
+                        (None, _) => return Err(ConfigError::at(start, "unterminated comment")),
+                    }
+                }
+            }
+            _ => return Ok(()),
+        }
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '*'
+}
+
+fn parse_settings(s: &mut Scanner, terminator: Option<char>) -> Result<Vec<(String, ConfigValue)>, ConfigError> {
+    let mut settings: Vec<(String, ConfigValue)> = Vec::new();
+    loop {
+        skip_whitespace(s)?;
+        match s.peek() {
+            None => match terminator {
+                Some(t) => return Err(s.error(format!("unexpected end of file, expected '{}'", t))),
+                None => return Ok(settings),
+            },
+            Some(c) if Some(c) == terminator => {
+                s.bump();
+                return Ok(settings);
+            }
+            Some('@') => return Err(s.error("directives like @include aren't supported")),
+            Some(c) if c.is_ascii_alphabetic() || c == '*' => {}
+            Some(c) => return Err(s.error(format!("unexpected '{}', expected a setting name", c))),
+        }
+        let position = s.position();
+        let mut name = String::new();
+        while let Some(c) = s.peek().filter(|&c| is_name_char(c)) {
+            name.push(c);
+            s.bump();
+        }
+        if settings.iter().any(|(n, _)| *n == name) {
+            return Err(ConfigError::at(position, format!("duplicate setting '{}'", name)));
+        }
+        skip_whitespace(s)?;
+        match s.peek() {
+            Some(':') | Some('=') => {
+                s.bump();
+            }
+            _ => return Err(s.error(format!("expected ':' or '=' after '{}'", name))),
+        }
+        skip_whitespace(s)?;
+        let value = parse_value(s)?;
+        skip_whitespace(s)?;
+        if let Some(';') | Some(',') = s.peek() {
+            s.bump();
+        }
+        settings.push((name, value));
+    }
+}
+
+fn parse_values(s: &mut Scanner, terminator: char) -> Result<Vec<ConfigValue>, ConfigError> {
+    let mut values = Vec::new();
+    loop {
+        skip_whitespace(s)?;
+        if s.peek() == Some(terminator) {
+            s.bump();
+            return Ok(values);
+        }
+        if !values.is_empty() {
+            match s.peek() {
+                Some(',') => {
+                    s.bump();
+                    skip_whitespace(s)?;
+                }
+                Some(c) => return Err(s.error(format!("unexpected '{}', expected ',' or '{}'", c, terminator))),
+                None => return Err(s.error(format!("unexpected end of file, expected '{}'", terminator))),
+            }
+        }
+        values.push(parse_value(s)?);
+    }
+}
+
+fn parse_value(s: &mut Scanner) -> Result<ConfigValue, ConfigError> {
+    let position = s.position();
+    let value = match s.peek() {
+        Some('{') => {
+            s.bump();
+            Value::Group(parse_settings(s, Some('}'))?)
+        }
+        Some('[') => {
+            s.bump();
+            Value::List(parse_values(s, ']')?)
+        }
+        Some('(') => {
+            s.bump();
+            Value::List(parse_values(s, ')')?)
+        }
+        Some('"') => {
+            // adjacent string literals are concatenated
+            let mut result = parse_string(s)?;
+            loop {
+                skip_whitespace(s)?;
+                if s.peek() != Some('"') {
+                    break;
+                }
+                result.push_str(&parse_string(s)?);
+            }
+            Value::Str(result)
+        }
+        Some(_) => {
+            let mut token = String::new();
+            while let Some(c) = s.peek().filter(|&c| c.is_ascii_alphanumeric() || "+-._".contains(c)) {
+                token.push(c);
+                s.bump();
+            }
+            match parse_scalar(&token) {
+                Ok(Some(value)) => value,
+                Ok(None) if token.is_empty() => return Err(s.error("expected a value")),
+                Ok(None) => return Err(ConfigError::at(position, format!("invalid value '{}'", token))),
+                Err(message) => return Err(ConfigError::at(position, format!("{} '{}'", message, token))),
+            }
+        }
+        None => return Err(s.error("unexpected end of file, expected a value")),
+    };
+    Ok(ConfigValue { value, position })
+}
+
+/// Parses a boolean or number, or returns None if the token isn't one, or an error if it's an integer which doesn't
+/// fit in 64 bits.
+fn parse_scalar(token: &str) -> Result<Option<Value>, &'static str> {
+    if token.eq_ignore_ascii_case("true") {
+        return Ok(Some(Value::Bool(true)));
+    }
+    if token.eq_ignore_ascii_case("false") {
+        return Ok(Some(Value::Bool(false)));
+    }
+    let digits = token.trim_end_matches(['L', 'l']);
+    let (negative, unsigned) = match digits.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, digits.strip_prefix('+').unwrap_or(digits)),
+    };
+    let magnitude = match unsigned.strip_prefix("0x").or_else(|| unsigned.strip_prefix("0X")) {
+        Some(hex) if !hex.is_empty() && hex.bytes().all(|b| b.is_ascii_hexdigit()) => Some(u64::from_str_radix(hex, 16)),
+        None if !unsigned.is_empty() && unsigned.bytes().all(|b| b.is_ascii_digit()) => Some(unsigned.parse::<u64>()),
+        _ => None,
+    };
+    if let Some(magnitude) = magnitude {
+        let int = magnitude.ok().and_then(|n| match i64::try_from(n) {
+            Ok(n) if negative => n.checked_neg(),
+            Ok(n) => Some(n),
+            // the magnitude of i64::MIN is one more than i64::MAX
+            Err(_) if negative && n == 1 << 63 => Some(i64::MIN),
+            Err(_) => None,
+        });
+        return int.map(|n| Some(Value::Int(n))).ok_or("integer out of range");
+    }
+    if token.starts_with(|c: char| c.is_ascii_digit() || "+-.".contains(c)) {
+        return Ok(token.parse::<f64>().ok().map(Value::Float));
+    }
+    Ok(None)
+}
+
+fn parse_string(s: &mut Scanner) -> Result<String, ConfigError> {
+    let start = s.position();
+    s.bump(); // opening quote
+    // `\x` escapes are bytes, which may be parts of multi-byte characters, so build the string as bytes
+    let mut result = Vec::new();
+    let push = |result: &mut Vec<u8>, c: char| result.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+    loop {
+        match s.bump() {
+            None | Some('\n') => return Err(ConfigError::at(start, "unterminated string")),
+            Some('"') => return String::from_utf8(result).map_err(|_| ConfigError::at(start, "string isn't valid UTF-8")),
+            Some('\\') => {
+                let position = s.position();
+                match s.bump() {
+                    Some('"') => push(&mut result, '"'),
+                    Some('\\') => push(&mut result, '\\'),
+                    Some('n') => push(&mut result, '\n'),
+                    Some('r') => push(&mut result, '\r'),
+                    Some('t') => push(&mut result, '\t'),
+                    Some('f') => push(&mut result, '\x0c'),
+                    Some('x') => {
+                        let hex: String = (0..2).filter_map(|_| s.bump()).collect();
+                        match u8::from_str_radix(&hex, 16) {
+                            Ok(b) => result.push(b),
+                            Err(_) => return Err(ConfigError::at(position, "invalid \\x escape")),
+                        }
+                    }
+                    _ => return Err(ConfigError::at(position, "invalid escape sequence")),
+                }
+            }
+            Some(c) => push(&mut result, c),
+        }
+    }
+}
+
+/// Parses the contents of a legacy INI-style `.cfg` file. Settings before the first `[section]` go into the
+/// top-level group, and every value is a string.
+pub fn parse_ini(input: &str) -> Result<ConfigValue, ConfigError> {
+    let top = Position { line: 1, column: 1 };
+    let mut root: Vec<(String, ConfigValue)> = Vec::new();
+    let mut section: Option<(String, ConfigValue)> = None;
+    for (i, raw_line) in input.lines().enumerate() {
+        let line = strip_ini_comment(raw_line);
+        let indent = line.len() - line.trim_start().len();
+        let trimmed = line.trim();
+        let position = Position { line: i + 1, column: raw_line[..indent].chars().count() + 1 };
+        if trimmed.is_empty() {
+            continue;
+        }
+        if trimmed.starts_with('[') {
+            let name = match trimmed.strip_suffix(']') {
+                Some(name) => name[1..].trim().to_owned(),
+                None => return Err(ConfigError::at(position, "expected ']' to close the section name")),
+            };
+            if let Some(finished) = section.take() {
+                root.push(finished);
+            }
+            if root.iter().any(|(n, _)| *n == name) {
+                return Err(ConfigError::at(position, format!("duplicate section '{}'", name)));
+            }
+            section = Some((name, ConfigValue { value: Value::Group(Vec::new()), position }));
+            continue;
+        }
+        let (key, value) = match trimmed.find('=') {
+            Some(i) => (trimmed[..i].trim(), trimmed[i + 1..].trim()),
+            None => return Err(ConfigError::at(position, "expected 'key = value'")),
+        };
+        let setting = (key.to_owned(), ConfigValue { value: Value::Str(value.replace("\\;", ";")), position });
+        match section {
+            Some((_, ConfigValue { value: Value::Group(ref mut settings), .. })) => settings.push(setting),
+            _ => root.push(setting),
+        }
+    }
+    if let Some(finished) = section.take() {
+        root.push(finished);
+    }
+    Ok(ConfigValue { value: Value::Group(root), position: top })
+}
+
+/// Strips any comment from an INI line. Comments start at a `#` or `;` at the beginning of the line, or at
+/// any other unescaped `;`.
+fn strip_ini_comment(line: &str) -> &str {
+    if line.trim_start().starts_with('#') {
+        return "";
+    }
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            ';' if !escaped => return &line[..i],
+            '\\' => escaped = !escaped,
+            _ => escaped = false,
+        }
+    }
+    line
+}
+
+/// Returns the path to the configuration file for the given plugin package in the given configuration
+/// directory, preferring `<package>.jcfg` to the legacy `<package>.cfg`, or None if neither exists.
+pub fn find(config_path: impl AsRef<Path>, package: &str) -> Option<PathBuf> {
+    ["jcfg", "cfg"]
+        .iter()
+        .map(|ext| config_path.as_ref().join(format!("{}.{}", package, ext)))
+        .find(|path| path.is_file())
+}
+
+fn not_found(config_path: &Path, package: &str) -> ConfigError {
+    let message = format!("no {}.jcfg or {}.cfg found in {}", package, package, config_path.display());
+    ConfigError { message, position: None, path: None }
+}
+
+/// Reads and parses the configuration file at the given path, choosing the format based on its extension.
+pub fn read(path: impl AsRef<Path>) -> Result<ConfigValue, ConfigError> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path).map_err(|e| <ConfigError as de::Error>::custom(e).in_file(path))?;
+    let result = match path.extension() {
+        Some(ext) if ext == "cfg" => parse_ini(&contents),
+        _ => parse_jcfg(&contents),
+    };
+    result.map_err(|e| e.in_file(path))
+}
+
+/// Deserializes a parsed configuration into any deserializable type.
+pub fn from_value<T: DeserializeOwned>(value: &ConfigValue) -> Result<T, ConfigError> {
+    T::deserialize(value)
+}
+
+/// Finds, reads and deserializes the configuration file for the given plugin package in the given
+/// configuration directory (i.e. the `config_path` which Janus passes to `init`.)
+pub fn load<T: DeserializeOwned>(config_path: impl AsRef<Path>, package: &str) -> Result<T, ConfigError> {
+    let config_path = config_path.as_ref();
+    let path = find(config_path, package).ok_or_else(|| not_found(config_path, package))?;
+    from_value(&read(&path)?).map_err(|e| e.in_file(&path))
+}
+
+/// How a setting differs between two versions of a configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// A setting which differs between two versions of a configuration, identified by its dotted path
+/// (e.g. `general.bitrate_cap`, or `rooms.2` for the third element of a list.) Lists are compared element by element,
+/// so inserting an element in the middle of a list changes every element after it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct ConfigChange {
+    pub setting: String,
+    pub change: ChangeKind,
+}
+
+/// Returns whether two settings have the same contents, wherever in their files they were found.
+fn same_contents(a: &ConfigValue, b: &ConfigValue) -> bool {
+    match (&a.value, &b.value) {
+        (Value::List(x), Value::List(y)) => x.len() == y.len() && x.iter().zip(y).all(|(x, y)| same_contents(x, y)),
+        (Value::Group(x), Value::Group(y)) => {
+            x.len() == y.len() && x.iter().all(|(name, x)| matches!(b.get(name), Some(y) if same_contents(x, y)))
+        }
+        (x, y) => x == y,
+    }
+}
+
+fn diff_into(prefix: &str, old: &ConfigValue, new: &ConfigValue, changes: &mut Vec<ConfigChange>) {
+    let path = |name: &str| if prefix.is_empty() { name.to_owned() } else { format!("{}.{}", prefix, name) };
+    match (&old.value, &new.value) {
+        (Value::Group(old_settings), Value::Group(new_settings)) => {
+            for (name, old_value) in old_settings {
+                match new.get(name) {
+                    Some(new_value) => diff_into(&path(name), old_value, new_value, changes),
+                    None => changes.push(ConfigChange { setting: path(name), change: ChangeKind::Removed }),
+                }
+            }
+            for (name, _) in new_settings.iter().filter(|(name, _)| old.get(name).is_none()) {
+                changes.push(ConfigChange { setting: path(name), change: ChangeKind::Added });
+            }
+        }
+        (Value::List(old_items), Value::List(new_items)) => {
+            for i in 0..old_items.len().max(new_items.len()) {
+                match (old_items.get(i), new_items.get(i)) {
+                    (Some(old_item), Some(new_item)) => diff_into(&path(&i.to_string()), old_item, new_item, changes),
+                    (Some(_), None) => changes.push(ConfigChange { setting: path(&i.to_string()), change: ChangeKind::Removed }),
+                    (None, _) => changes.push(ConfigChange { setting: path(&i.to_string()), change: ChangeKind::Added }),
+                }
+            }
+        }
+        _ if !same_contents(old, new) => changes.push(ConfigChange { setting: prefix.to_owned(), change: ChangeKind::Changed }),
+        _ => {}
+    }
+}
+
+/// Lists every setting which was added, removed or changed between two parsed configurations.
+pub fn diff(old: &ConfigValue, new: &ConfigValue) -> Vec<ConfigChange> {
+    let mut changes = Vec::new();
+    diff_into("", old, new, &mut changes);
+    changes
+}
+
+type Validator<C> = Box<dyn Fn(&C) -> Result<(), String> + Send + Sync>;
+type ChangeHandler<C> = Box<dyn Fn(&C, &C, &[ConfigChange]) + Send + Sync>;
+
+#[derive(Debug)]
+struct Loaded<C> {
+    config: Arc<C>,
+    raw: ConfigValue,
+}
+
+/// Holds a plugin's current configuration and reloads it from disk on request, so that configuration
+/// changes can be applied without restarting Janus. Reloads are triggered by calling `reload`, or by the
+/// `reload_config` admin command once `register_admin_command` has added it to an `AdminRegistry`.
+pub struct ConfigReloader<C> {
+    path: PathBuf,
+    current: RwLock<Loaded<C>>,
+    reloading: Mutex<()>,
+    validate: Validator<C>,
+    on_change: ChangeHandler<C>,
+}
+
+/// The admin request which triggers a configuration reload.
+pub const RELOAD_CONFIG_REQUEST: &str = "reload_config";
+
+#[derive(Debug, Serialize)]
+struct ReloadResponse {
+    changes: Vec<ConfigChange>,
+}
+
+impl<C: DeserializeOwned> ConfigReloader<C> {
+    /// Loads the configuration for the given plugin package from the given configuration directory, like
+    /// `load` does, remembering where it came from so that it can be reloaded later.
+    pub fn load(config_path: impl AsRef<Path>, package: &str) -> Result<Self, ConfigError> {
+        let config_path = config_path.as_ref();
+        Self::load_file(find(config_path, package).ok_or_else(|| not_found(config_path, package))?)
+    }
+
+    /// Loads the configuration from the given file.
+    pub fn load_file(path: impl Into<PathBuf>) -> Result<Self, ConfigError> {
+        let path = path.into();
+        let current = RwLock::new(Self::read_config(&path)?);
+        Ok(Self {
+            path,
+            current,
+            reloading: Mutex::new(()),
+            validate: Box::new(|_| Ok(())),
+            on_change: Box::new(|_, _, _| {}),
+        })
+    }
+
+    /// Sets a function which checks each newly loaded configuration before it replaces the current one. If it
+    /// returns an error, the reload fails and the current configuration is kept.
+    pub fn validate_with<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&C) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.validate = Box::new(f);
+        self
+    }
+
+    /// Sets a function which is called with the old configuration, the new configuration, and the list of
+    /// changed settings whenever a reload changes anything, so that the plugin can apply the changes live.
+    pub fn on_change<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&C, &C, &[ConfigChange]) + Send + Sync + 'static,
+    {
+        self.on_change = Box::new(f);
+        self
+    }
+
+    fn read_config(path: &Path) -> Result<Loaded<C>, ConfigError> {
+        let raw = read(path)?;
+        let config = Arc::new(from_value(&raw).map_err(|e| e.in_file(path))?);
+        Ok(Loaded { config, raw })
+    }
+
+    /// Returns the path of the configuration file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the current configuration.
+    pub fn current(&self) -> Arc<C> {
+        Arc::clone(&self.current.read().unwrap().config)
+    }
+
+    /// Re-reads the configuration file. If the new configuration is valid, it replaces the current one, and if
+    /// anything changed, the change handler is called. Returns the list of changed settings.
+    pub fn reload(&self) -> Result<Vec<ConfigChange>, ConfigError> {
+        let _reloading = self.reloading.lock().unwrap();
+        let loaded = Self::read_config(&self.path)?;
+        (self.validate)(&loaded.config).map_err(|message| ConfigError { message, position: None, path: Some(self.path.clone()) })?;
+        let changes = diff(&self.current.read().unwrap().raw, &loaded.raw);
+        if !changes.is_empty() {
+            let new_config = Arc::clone(&loaded.config);
+            let old = std::mem::replace(&mut *self.current.write().unwrap(), loaded);
+            (self.on_change)(&old.config, &new_config, &changes);
+        }
+        Ok(changes)
+    }
+
+    /// Registers the `reload_config` admin command, which reloads the configuration and responds with
+    /// `{"result": "ok", "changes": [...]}`, or with the error if the new configuration couldn't be loaded.
+    pub fn register_admin_command<'a>(self: &Arc<Self>, registry: &'a mut AdminRegistry) -> &'a mut AdminRegistry
+    where
+        C: Send + Sync + 'static,
+    {
+        let reloader = Arc::clone(self);
+        registry.register(RELOAD_CONFIG_REQUEST, "Reloads the plugin's configuration file.", move |_: serde_json::Value| {
+            reloader.reload().map(|changes| ReloadResponse { changes }).map_err(|e| e.to_string())
+        })
+    }
+}
+
+impl<C: fmt::Debug> fmt::Debug for ConfigReloader<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ConfigReloader {{ path: {:?}, current: {:?} }}", self.path, self.current.read().unwrap().config)
+    }
+}
+
+/// Interprets a string the way Janus does when it expects a boolean.
+fn parse_bool(s: &str) -> Option<bool> {
+    match s.to_ascii_lowercase().as_str() {
+        "yes" | "true" | "1" | "on" => Some(true),
+        "no" | "false" | "0" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+struct SeqAccess<'de> {
+    iter: std::slice::Iter<'de, ConfigValue>,
+}
+
+impl<'de> de::SeqAccess<'de> for SeqAccess<'de> {
+    type Error = ConfigError;
+
+    fn next_element_seed<S: de::DeserializeSeed<'de>>(&mut self, seed: S) -> Result<Option<S::Value>, ConfigError> {
+        self.iter.next().map(|v| seed.deserialize(v)).transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct MapAccess<'de> {
+    iter: std::slice::Iter<'de, (String, ConfigValue)>,
+    value: Option<&'de ConfigValue>,
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess<'de> {
+    type Error = ConfigError;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, ConfigError> {
+        match self.iter.next() {
+            Some((name, value)) => {
+                self.value = Some(value);
+                seed.deserialize(name.as_str().into_deserializer()).map(Some).map_err(|e| value.locate(e))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, ConfigError> {
+        let value = self.value.take().expect("next_value_seed called before next_key_seed");
+        seed.deserialize(value)
+    }
+}
+
+struct EnumAccess<'de> {
+    variant: &'de str,
+    value: Option<&'de ConfigValue>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess<'de> {
+    type Error = ConfigError;
+    type Variant = Self;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), ConfigError> {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for EnumAccess<'de> {
+    type Error = ConfigError;
+
+    fn unit_variant(self) -> Result<(), ConfigError> {
+        match self.value {
+            None => Ok(()),
+            Some(v) => Err(de::Error::invalid_type(v.unexpected(), &"a unit variant")),
+        }
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, ConfigError> {
+        match self.value {
+            Some(v) => seed.deserialize(v),
+            None => Err(de::Error::invalid_type(de::Unexpected::UnitVariant, &"a newtype variant")),
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, ConfigError> {
+        match self.value {
+            Some(v) => v.deserialize_seq(visitor),
+            None => Err(de::Error::invalid_type(de::Unexpected::UnitVariant, &"a tuple variant")),
+        }
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, ConfigError> {
+        match self.value {
+            Some(v) => v.deserialize_map(visitor),
+            None => Err(de::Error::invalid_type(de::Unexpected::UnitVariant, &"a struct variant")),
+        }
+    }
+}
+
+macro_rules! deserialize_number {
+    ($method:ident, $visit:ident, $ty:ty) => {
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConfigError> {
+            let result = match self.value {
+                Value::Int(i) => visitor.visit_i64(i),
+                Value::Float(f) => visitor.visit_f64(f),
+                // values from INI files are all strings
+                Value::Str(ref s) => match s.parse::<$ty>() {
+                    Ok(n) => visitor.$visit(n),
+                    Err(_) => Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+                },
+                _ => Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+            };
+            result.map_err(|e| self.locate(e))
+        }
+    };
+}
+
+impl<'de> Deserializer<'de> for &'de ConfigValue {
+    type Error = ConfigError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConfigError> {
+        let result = match self.value {
+            Value::Bool(b) => visitor.visit_bool(b),
+            Value::Int(i) => visitor.visit_i64(i),
+            Value::Float(f) => visitor.visit_f64(f),
+            Value::Str(ref s) => visitor.visit_borrowed_str(s),
+            Value::List(ref values) => visitor.visit_seq(SeqAccess { iter: values.iter() }),
+            Value::Group(ref settings) => visitor.visit_map(MapAccess { iter: settings.iter(), value: None }),
+        };
+        result.map_err(|e| self.locate(e))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConfigError> {
+        let result = match self.value {
+            Value::Bool(b) => visitor.visit_bool(b),
+            Value::Str(ref s) => match parse_bool(s) {
+                Some(b) => visitor.visit_bool(b),
+                None => Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+            },
+            _ => Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+        };
+        result.map_err(|e| self.locate(e))
+    }
+
+    deserialize_number!(deserialize_i8, visit_i8, i8);
+    deserialize_number!(deserialize_i16, visit_i16, i16);
+    deserialize_number!(deserialize_i32, visit_i32, i32);
+    deserialize_number!(deserialize_i64, visit_i64, i64);
+    deserialize_number!(deserialize_u8, visit_u8, u8);
+    deserialize_number!(deserialize_u16, visit_u16, u16);
+    deserialize_number!(deserialize_u32, visit_u32, u32);
+    deserialize_number!(deserialize_u64, visit_u64, u64);
+    deserialize_number!(deserialize_f32, visit_f32, f32);
+    deserialize_number!(deserialize_f64, visit_f64, f64);
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConfigError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, ConfigError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ConfigError> {
+        let result = match self.value {
+            Value::Str(ref s) => visitor.visit_enum(EnumAccess { variant: s, value: None }),
+            Value::Group(ref settings) if settings.len() == 1 => {
+                let (ref variant, ref value) = settings[0];
+                visitor.visit_enum(EnumAccess { variant, value: Some(value) })
+            }
+            _ => Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+        };
+        result.map_err(|e| self.locate(e))
+    }
+
+    serde::forward_to_deserialize_any! {
+        i128 u128 char str string bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct General {
+        enabled: bool,
+        max_rooms: u32,
+        bitrate_cap: Option<f64>,
+        name: String,
+        rooms: Vec<String>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Config {
+        general: General,
+    }
+
+    #[test]
+    fn parse_jcfg_settings() {
+        let input = r#"
+            # a comment
+            general: {
+                enabled = true;
+                max_rooms = 0x10 // sixteen
+                /* multi-line
+                   comment */
+                bitrate_cap: 1.5e6,
+                name = "my " "room\t\"plugin\""
+                rooms = [ "a", "b" ]
+            }
+        "#;
+        let config: Config = from_value(&parse_jcfg(input).unwrap()).unwrap();
+        assert_eq!(
+            config.general,
+            General {
+                enabled: true,
+                max_rooms: 16,
+                bitrate_cap: Some(1_500_000.0),
+                name: "my room\t\"plugin\"".into(),
+                rooms: vec!["a".into(), "b".into()],
+            }
+        );
+    }
+
+    #[test]
+    fn parse_integer_limits() {
+        let int = |token: &str| parse_jcfg(&format!("x = {}", token)).map(|v| v.get("x").unwrap().value.clone());
+        assert_eq!(int("-0x8000000000000000").unwrap(), Value::Int(i64::MIN));
+        assert_eq!(int("-9223372036854775808").unwrap(), Value::Int(i64::MIN));
+        assert_eq!(int("0x7fffffffffffffff").unwrap(), Value::Int(i64::MAX));
+        for token in &["0x8000000000000000", "-0x8000000000000001", "9223372036854775808", "0x10000000000000000"] {
+            let error = int(token).unwrap_err();
+            assert_eq!(error.message, format!("integer out of range '{}'", token));
+            assert_eq!(error.position, Some(Position { line: 1, column: 5 }));
+        }
+    }
+
+    #[test]
+    fn parse_ini_settings() {
+        let input = "; a comment\n[general]\nenabled = yes\nmax_rooms = 16 ; inline\nname = semi\\;colon\nrooms = x\n";
+        let value = parse_ini(input).unwrap();
+        let general = value.get("general").unwrap();
+        assert_eq!(general.get("name").unwrap().value, Value::Str("semi;colon".into()));
+        assert_eq!(general.get("max_rooms").unwrap().value, Value::Str("16".into()));
+        assert_eq!(general.get("max_rooms").unwrap().position, Position { line: 4, column: 1 });
+
+        #[derive(Debug, Deserialize)]
+        struct IniGeneral {
+            enabled: bool,
+            max_rooms: u32,
+        }
+        let config: IniGeneral = from_value(general).unwrap();
+        assert!(config.enabled);
+        assert_eq!(config.max_rooms, 16);
+    }
+
+    #[test]
+    fn diff_settings() {
+        let old = parse_jcfg("general: { a = 1; b = [1, 2]; c = \"x\" }\nrooms: { }").unwrap();
+        let new = parse_jcfg("general: {\n  b = [1, 2]\n  a = 2\n  d = true\n}").unwrap();
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                ConfigChange { setting: "general.a".into(), change: ChangeKind::Changed },
+                ConfigChange { setting: "general.c".into(), change: ChangeKind::Removed },
+                ConfigChange { setting: "general.d".into(), change: ChangeKind::Added },
+                ConfigChange { setting: "rooms".into(), change: ChangeKind::Removed },
+            ]
+        );
+        assert!(diff(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn diff_lists() {
+        let old = parse_jcfg("rooms = ( { id = 1 }, { id = 2; pin = \"x\" }, { id = 3 } )\nports = [ 1, 2 ]").unwrap();
+        let new = parse_jcfg("rooms = ( { id = 1 }, { id = 2; pin = \"y\" } )\nports = [ 1, 2, 3 ]").unwrap();
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                ConfigChange { setting: "rooms.1.pin".into(), change: ChangeKind::Changed },
+                ConfigChange { setting: "rooms.2".into(), change: ChangeKind::Removed },
+                ConfigChange { setting: "ports.2".into(), change: ChangeKind::Added },
+            ]
+        );
+    }
+
+    #[test]
+    fn reload_changed_config() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        #[derive(Debug, Deserialize)]
+        struct Limits {
+            bitrate: u32,
+        }
+        let path = std::env::temp_dir().join(format!("janus-plugin-reload-{}.jcfg", std::process::id()));
+        fs::write(&path, "bitrate = 1000").unwrap();
+        let seen = Arc::new(AtomicUsize::new(0));
+        let seen_by_handler = Arc::clone(&seen);
+        let reloader = ConfigReloader::<Limits>::load_file(&path)
+            .unwrap()
+            .validate_with(|c| if c.bitrate > 0 { Ok(()) } else { Err("bitrate must be positive".into()) })
+            .on_change(move |old, new, _| {
+                assert_eq!(old.bitrate, 1000);
+                seen_by_handler.store(new.bitrate as usize, Ordering::SeqCst);
+            });
+
+        assert!(reloader.reload().unwrap().is_empty());
+        fs::write(&path, "bitrate = 0").unwrap();
+        assert_eq!(reloader.reload().unwrap_err().message, "bitrate must be positive");
+        assert_eq!(reloader.current().bitrate, 1000);
+
+        let reloader = Arc::new(reloader);
+        let mut registry = AdminRegistry::new();
+        reloader.register_admin_command(&mut registry);
+        let request = crate::jansson::JanssonValue::from_serde(&serde_json::json!({ "request": "reload_config" })).unwrap();
+        let reload = || registry.dispatch(&request).to_serde::<serde_json::Value>().unwrap();
+        fs::write(&path, "bitrate = 2000").unwrap();
+        assert_eq!(reload(), serde_json::json!({ "result": "ok", "changes": [{ "setting": "bitrate", "change": "changed" }] }));
+        assert_eq!(seen.load(Ordering::SeqCst), 2000);
+        assert_eq!(reloader.current().bitrate, 2000);
+
+        fs::write(&path, "bitrate = 0").unwrap();
+        assert_eq!(reload()["result"], "error");
+        assert_eq!(reloader.current().bitrate, 2000);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn report_syntax_error_positions() {
+        let err = parse_jcfg("general: {\n  enabled = true\n  max_rooms = ;\n}").unwrap_err();
+        assert_eq!(err.position, Some(Position { line: 3, column: 15 }));
+
+        let err = parse_jcfg("general: {\n  name = \"unterminated\n}").unwrap_err();
+        assert_eq!(err.position, Some(Position { line: 2, column: 10 }));
+        assert_eq!(err.to_string(), "line 2, column 10: unterminated string");
+
+        let err = parse_jcfg("name = \"caf\\xc3\"").unwrap_err();
+        assert_eq!(err.to_string(), "line 1, column 8: string isn't valid UTF-8");
+    }
+
+    #[test]
+    fn decode_byte_escapes_as_utf8() {
+        let name = |input: &str| parse_jcfg(input).unwrap().get("name").unwrap().value.clone();
+        assert_eq!(name(r#"name = "caf\xc3\xa9 \x41""#), Value::Str("café A".into()));
+        assert_eq!(name(r#"name = "café""#), Value::Str("café".into()));
+    }
+
+    #[test]
+    fn report_type_error_positions() {
+        let input = "general: {\n  enabled = true\n  max_rooms = \"lots\"\n  name = \"x\"\n  rooms = []\n}";
+        let err = from_value::<Config>(&parse_jcfg(input).unwrap()).unwrap_err();
+        assert_eq!(err.position, Some(Position { line: 3, column: 15 }));
+    }
+}
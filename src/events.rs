@@ -0,0 +1,87 @@
+/// Utilities to notify Janus's event handlers about things happening in a plugin.
+use crate::jansson::JanssonValue;
+use crate::session::SessionWrapper;
+use crate::{Plugin, PluginCallbacks, PluginSession};
+use serde::ser::Serialize;
+use std::error::Error;
+use std::ptr;
+
+/// Emits plugin events to whatever event handlers are loaded into Janus.
+#[derive(Debug, Clone, Copy)]
+pub struct EventEmitter {
+    callbacks: &'static PluginCallbacks,
+    plugin: &'static Plugin,
+}
+
+impl EventEmitter {
+    /// Creates an emitter for the given plugin, using the callbacks which Janus passed to `init`.
+    pub fn new(callbacks: &'static PluginCallbacks, plugin: &'static Plugin) -> Self {
+        Self { callbacks, plugin }
+    }
+
+    /// Returns whether Janus has any event handlers enabled.
+    pub fn is_enabled(&self) -> bool {
+        (self.callbacks.events_is_enabled)() != 0
+    }
+
+    /// Sends an event to Janus's event handlers, either for a specific session or, if no session is given, for the
+    /// plugin as a whole. The event should serialize to a JSON object. If event handlers are disabled, the event
+    /// isn't serialized at all. Returns whether the event was sent.
+    pub fn emit_event<T>(&self, session: Option<&SessionWrapper<T>>, event: impl Serialize) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        self.notify(session.map_or(ptr::null_mut(), |s| s.as_ptr()), event)
+    }
+
+    /// Sends an event about the plugin as a whole to Janus's event handlers. Equivalent to `emit_event` without
+    /// a session, but doesn't need the session type spelled out.
+    pub fn emit_plugin_event(&self, event: impl Serialize) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        self.notify(ptr::null_mut(), event)
+    }
+
+    fn notify(&self, handle: *mut PluginSession, event: impl Serialize) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        if !self.is_enabled() {
+            return Ok(false);
+        }
+        let json = JanssonValue::from_serde(&event)?;
+        let plugin = self.plugin as *const Plugin as *mut Plugin;
+        // the Janus core takes over our reference to the event
+        (self.callbacks.notify_event)(plugin, handle, json.into_raw());
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::janus_stubs;
+    use crate::RawJanssonValue;
+    use std::os::raw::c_int;
+    use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+
+    static ENABLED: AtomicI32 = AtomicI32::new(0);
+    static NOTIFIED: AtomicUsize = AtomicUsize::new(0);
+
+    extern "C" fn events_is_enabled() -> c_int {
+        ENABLED.load(Ordering::SeqCst)
+    }
+
+    extern "C" fn notify_event(_plugin: *mut Plugin, _handle: *mut PluginSession, event: *mut RawJanssonValue) {
+        drop(unsafe { JanssonValue::from_raw(event) });
+        NOTIFIED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn emit_only_when_enabled() {
+        let callbacks = PluginCallbacks { events_is_enabled, notify_event, ..janus_stubs::callbacks() };
+        let emitter = EventEmitter::new(Box::leak(Box::new(callbacks)), &janus_stubs::PLUGIN);
+        assert!(!emitter.is_enabled());
+        assert!(!emitter.emit_plugin_event(serde_json::json!({ "event": "started" })).unwrap());
+        assert_eq!(NOTIFIED.load(Ordering::SeqCst), 0);
+
+        // Janus reports whether events are enabled as a gboolean, so any nonzero value means they are
+        ENABLED.store(2, Ordering::SeqCst);
+        assert!(emitter.is_enabled());
+        assert!(emitter.emit_plugin_event(serde_json::json!({ "event": "started" })).unwrap());
+        assert_eq!(NOTIFIED.load(Ordering::SeqCst), 1);
+    }
+}
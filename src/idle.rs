@@ -0,0 +1,160 @@
+/// Utilities to detect and clean up sessions whose clients went away without hanging up.
+use crate::session::{SessionRegistry, SessionWrapper};
+use crate::PluginCallbacks;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Controls when a session is considered idle and what happens to it when it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdleTimeout {
+    /// How long a session may go without any activity before it expires.
+    pub timeout: Duration,
+    /// How often to check for expired sessions.
+    pub check_interval: Duration,
+    /// Whether to ask Janus to close the PeerConnection of an expired session.
+    pub close_pc: bool,
+    /// Whether to ask Janus to end an expired session entirely.
+    pub end_session: bool,
+}
+
+impl IdleTimeout {
+    /// Creates a timeout which expires sessions after the given idle time, without closing anything.
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout, check_interval: Duration::from_secs(1), close_pc: false, end_session: false }
+    }
+}
+
+/// Makes one pass over the registry, calling `on_expire` for every session that has been idle for longer
+/// than the timeout (i.e. which hasn't been `touch`ed; see `IdleReaper`) and then closing it as configured. Expired sessions have their idle time reset, so a
+/// session which stays open will only expire again after another full timeout. Returns the number of
+/// sessions which expired.
+pub fn sweep<T, F>(registry: &SessionRegistry<T>, config: &IdleTimeout, callbacks: Option<&PluginCallbacks>, mut on_expire: F) -> usize
+where
+    F: FnMut(&Arc<SessionWrapper<T>>),
+{
+    let mut expired = 0;
+    for session in registry.sessions() {
+        if session.idle_for() < config.timeout {
+            continue;
+        }
+        expired += 1;
+        session.touch();
+        on_expire(&session);
+        if let Some(cb) = callbacks {
+            if config.close_pc {
+                (cb.close_pc)(session.as_ptr());
+            }
+            if config.end_session {
+                (cb.end_session)(session.as_ptr());
+            }
+        }
+    }
+    expired
+}
+
+/// A background thread which periodically sweeps a session registry for idle sessions. The thread is
+/// stopped when the reaper is stopped or dropped.
+///
+/// Nothing records session activity automatically: the plugin must call `SessionWrapper::touch` from its callbacks
+/// whenever a session does something, or every session will expire one timeout after it was created, however busy
+/// it is. For example:
+///
+/// ```ignore
+/// extern "C" fn handle_message(handle: *mut PluginSession, transaction: *mut c_char,
+///                              message: *mut RawJanssonValue, jsep: *mut RawJanssonValue) -> *mut RawPluginResult {
+///     if let Ok(session) = unsafe { Session::from_ptr(handle) } {
+///         session.touch();
+///     }
+///     // ...
+/// }
+///
+/// extern "C" fn incoming_rtp(handle: *mut PluginSession, packet: *mut PluginRtpPacket) {
+///     if let Ok(session) = unsafe { Session::from_ptr(handle) } {
+///         session.touch();
+///     }
+///     // ...
+/// }
+/// ```
+#[derive(Debug)]
+pub struct IdleReaper {
+    stopped: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl IdleReaper {
+    /// Starts a reaper for the sessions in the given registry. The callbacks are used to close expired
+    /// sessions, if the timeout is configured to do so.
+    pub fn spawn<T, F>(
+        registry: Arc<SessionRegistry<T>>,
+        config: IdleTimeout,
+        callbacks: Option<&'static PluginCallbacks>,
+        mut on_expire: F,
+    ) -> io::Result<Self>
+    where
+        T: Send + Sync + 'static,
+        F: FnMut(&Arc<SessionWrapper<T>>) + Send + 'static,
+    {
+        let stopped = Arc::new((Mutex::new(false), Condvar::new()));
+        let signal = Arc::clone(&stopped);
+        let thread = thread::Builder::new().name("idle reaper".into()).spawn(move || {
+            let (lock, cvar) = &*signal;
+            let mut is_stopped = lock.lock().unwrap();
+            while !*is_stopped {
+                is_stopped = cvar.wait_timeout(is_stopped, config.check_interval).unwrap().0;
+                if !*is_stopped {
+                    // don't make stopping the reaper wait for a sweep, which calls back into the plugin and Janus
+                    drop(is_stopped);
+                    sweep(&registry, &config, callbacks, &mut on_expire);
+                    is_stopped = lock.lock().unwrap();
+                }
+            }
+        })?;
+        Ok(Self { stopped, thread: Some(thread) })
+    }
+
+    /// Stops the reaper thread and waits for it to exit.
+    pub fn stop(mut self) {
+        self.shut_down();
+    }
+
+    fn shut_down(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            let (lock, cvar) = &*self.stopped;
+            *lock.lock().unwrap() = true;
+            cvar.notify_all();
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for IdleReaper {
+    fn drop(&mut self) {
+        self.shut_down();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::session::test_handle;
+
+    #[test]
+    fn expires_idle_sessions() {
+        let mut handle = test_handle();
+        let registry = SessionRegistry::new();
+        let session = unsafe { SessionWrapper::associate(&mut handle, ()).unwrap() };
+        registry.insert(Arc::clone(&session));
+
+        let mut expired = Vec::new();
+        let config = IdleTimeout::new(Duration::from_secs(60));
+        assert_eq!(sweep(&registry, &config, None, |s| expired.push(s.as_ptr())), 0);
+
+        let config = IdleTimeout::new(Duration::from_secs(0));
+        assert_eq!(sweep(&registry, &config, None, |s| expired.push(s.as_ptr())), 1);
+        assert_eq!(expired, vec![&mut handle as *mut _]);
+        registry.clear();
+    }
+}
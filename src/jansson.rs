@@ -2,6 +2,8 @@
 
 use bitflags::bitflags;
 use jansson_sys;
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
 use std::error::Error;
 use std::fmt;
 use std::ffi::{CStr, CString};
@@ -91,6 +93,17 @@ impl JanssonValue {
         }
     }
 
+    /// Serializes any serializable Rust value into a Jansson value.
+    pub fn from_serde<T: Serialize + ?Sized>(value: &T) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Self::from_str(&serde_json::to_string(value)?, JanssonDecodingFlags::JSON_DECODE_ANY)
+    }
+
+    /// Deserializes this Jansson value into any deserializable Rust value.
+    pub fn to_serde<T: DeserializeOwned>(&self) -> Result<T, Box<dyn Error + Send + Sync>> {
+        let json = self.to_libcstring(JanssonEncodingFlags::JSON_ENCODE_ANY);
+        Ok(serde_json::from_slice(json.to_bytes())?)
+    }
+
     /// Encodes this Jansson value as a JSON owned C-style string.
     pub fn to_libcstring(&self, encoding_flags: JanssonEncodingFlags) -> LibcString {
         unsafe {
@@ -141,6 +154,24 @@ mod tests {
         assert_eq!(json, result.to_libcstring(JanssonEncodingFlags::JSON_SORT_KEYS).to_str().unwrap());
     }
 
+    #[test]
+    fn serde_round_trip() {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Message {
+            request: String,
+            room: Option<u64>,
+            tags: Vec<String>,
+        }
+        let message = Message { request: "join".into(), room: Some(1234), tags: vec!["a".into()] };
+        let result = JanssonValue::from_serde(&message).unwrap();
+        assert_eq!(
+            r#"{"request": "join", "room": 1234, "tags": ["a"]}"#,
+            result.to_libcstring(JanssonEncodingFlags::JSON_SORT_KEYS).to_str().unwrap()
+        );
+        assert_eq!(message, result.to_serde::<Message>().unwrap());
+        assert!(JanssonValue::from_serde(&42).unwrap().to_serde::<Message>().is_err());
+    }
+
     #[test]
     fn produce_jansson_errors() {
         let json = r#"{"a":"#;
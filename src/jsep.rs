@@ -0,0 +1,121 @@
+/// Types for the JSEP envelopes which carry SDP offers and answers alongside signalling messages.
+use crate::jansson::JanssonValue;
+use crate::sdp::Sdp;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::error::Error;
+
+/// Whether a JSEP carries an offer or an answer. Janus rejects other types, like `rollback`, before they reach plugins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JsepType {
+    Offer,
+    Answer,
+}
+
+/// A JSEP offer or answer, e.g. `{ "type": "offer", "sdp": "...", "trickle": false }`. Deserializing a JSEP parses
+/// its SDP, and serializing one writes it back out.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Jsep {
+    #[serde(rename = "type")]
+    pub kind: JsepType,
+    pub sdp: Sdp,
+    /// Whether the sender will trickle its ICE candidates; if absent, Janus assumes it will.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trickle: Option<bool>,
+    /// Whether the media is end-to-end encrypted with insertable streams.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub e2ee: Option<bool>,
+    /// Whether this is a renegotiation of an existing PeerConnection, which Janus flags on offers it passes on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update: Option<bool>,
+    /// Every other field, e.g. the `simulcast` description Janus adds, kept as is so that it survives a round trip.
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+impl Jsep {
+    /// Creates a JSEP containing an SDP offer.
+    pub fn offer(sdp: Sdp) -> Self {
+        Self { kind: JsepType::Offer, sdp, trickle: None, e2ee: None, update: None, other: Map::new() }
+    }
+
+    /// Creates a JSEP containing an SDP answer.
+    pub fn answer(sdp: Sdp) -> Self {
+        Self { kind: JsepType::Answer, sdp, trickle: None, e2ee: None, update: None, other: Map::new() }
+    }
+
+    /// Parses a JSEP from the JSON which Janus passes to `handle_message`.
+    pub fn from_jansson(value: &JanssonValue) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        value.to_serde()
+    }
+
+    /// Writes this JSEP into JSON suitable for passing to `push_event`.
+    pub fn to_jansson(&self) -> Result<JanssonValue, Box<dyn Error + Send + Sync>> {
+        JanssonValue::from_serde(self)
+    }
+}
+
+#[cfg(all(test, feature = "rust-sdp"))]
+mod tests {
+
+    use super::*;
+    use crate::jansson::{JanssonDecodingFlags, JanssonEncodingFlags};
+    use std::ffi::CString;
+
+    fn jansson(json: &str) -> JanssonValue {
+        JanssonValue::from_str(json, JanssonDecodingFlags::empty()).unwrap()
+    }
+
+    fn chrome_sdp() -> Sdp {
+        Sdp::parse(&CString::new(include_str!("sdp/testdata/chrome.sdp")).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut offer = Jsep::offer(chrome_sdp());
+        offer.trickle = Some(false);
+        offer.e2ee = Some(true);
+        let value = offer.to_jansson().unwrap();
+        let parsed = Jsep::from_jansson(&value).unwrap();
+        assert_eq!((parsed.kind, parsed.trickle, parsed.e2ee), (JsepType::Offer, Some(false), Some(true)));
+        assert_eq!(parsed.sdp.to_glibstring().to_str(), chrome_sdp().to_glibstring().to_str());
+
+        let answer = Jsep::answer(chrome_sdp());
+        let value = answer.to_jansson().unwrap();
+        let json = value.to_libcstring(JanssonEncodingFlags::JSON_SORT_KEYS);
+        let json = json.to_str().unwrap();
+        assert!(json.starts_with(r#"{"sdp": "v=0\r\n"#));
+        assert!(json.ends_with(r#""type": "answer"}"#));
+        assert!(!json.contains(r#""trickle""#) && !json.contains(r#""e2ee""#));
+        let parsed = Jsep::from_jansson(&value).unwrap();
+        assert_eq!((parsed.kind, parsed.trickle, parsed.e2ee), (JsepType::Answer, None, None));
+    }
+
+    #[test]
+    fn reject_invalid_jseps() {
+        let sdp = chrome_sdp().to_glibstring().to_str().unwrap().replace("\r\n", "\\r\\n");
+        let value = jansson(&format!(r#"{{"type": "offer", "sdp": "{}", "trickle": true}}"#, sdp));
+        assert_eq!(Jsep::from_jansson(&value).unwrap().trickle, Some(true));
+        assert!(Jsep::from_jansson(&jansson(r#"{"type": "offer", "trickle": true}"#)).is_err());
+        assert!(Jsep::from_jansson(&jansson(r#"{"type": "answer", "sdp": "not an sdp"}"#)).is_err());
+        assert!(Jsep::from_jansson(&jansson(r#"{"type": "rollback"}"#)).is_err());
+        assert!(Jsep::from_jansson(&jansson(&format!(r#"{{"type": "rollback", "sdp": "{}"}}"#, sdp))).is_err());
+    }
+
+    #[test]
+    fn keep_other_fields() {
+        let sdp = chrome_sdp().to_glibstring().to_str().unwrap().replace("\r\n", "\\r\\n");
+        let simulcast = r#"{"rids": ["h", "m", "l"], "rid-ext": 10}"#;
+        let json = format!(r#"{{"type": "offer", "sdp": "{}", "update": true, "simulcast": {}, "x-custom": 1}}"#, sdp, simulcast);
+        let parsed = Jsep::from_jansson(&jansson(&json)).unwrap();
+        assert_eq!(parsed.update, Some(true));
+        assert_eq!(parsed.other.keys().collect::<Vec<_>>(), vec!["simulcast", "x-custom"]);
+
+        let written = parsed.to_jansson().unwrap().to_serde::<Value>().unwrap();
+        assert_eq!(written["update"], true);
+        assert_eq!(written["simulcast"], serde_json::from_str::<Value>(simulcast).unwrap());
+        assert_eq!(written["x-custom"], 1);
+        assert_eq!(written.as_object().unwrap().len(), 5);
+    }
+}
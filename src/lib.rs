@@ -5,7 +5,7 @@ use bitflags::bitflags;
 pub use debug::LogLevel;
 pub use debug::log;
 pub use jansson::{JanssonDecodingFlags, JanssonEncodingFlags, JanssonValue, RawJanssonValue};
-pub use session::SessionWrapper;
+pub use session::{SessionRegistry, SessionWrapper};
 pub use ffi::events::janus_eventhandler as EventHandler;
 pub use ffi::plugin::janus_callbacks as PluginCallbacks;
 pub use ffi::plugin::janus_plugin as Plugin;
@@ -24,14 +24,178 @@ use std::ops::Deref;
 use std::os::raw::{c_char, c_int};
 use std::ptr;
 
+pub mod admin;
+pub mod auth;
+pub mod config;
 pub mod debug;
+pub mod events;
+pub mod idle;
+pub mod persist;
+pub mod ratelimit;
 pub mod rtcp;
+pub mod scheduler;
 pub mod sdp;
 pub mod session;
+pub mod shutdown;
 pub mod jansson;
+pub mod jsep;
+pub mod metrics;
 pub mod utils;
 pub mod refcount;
 
+// stand-ins for the Janus core symbols which this library refers to, so that the unit tests link without libjanus
+#[cfg(test)]
+mod janus_stubs {
+    use crate::ffi::plugin::{janus_plugin_data, janus_plugin_rtcp, janus_plugin_rtp};
+    use crate::{Plugin, PluginCallbacks, PluginResultType, PluginSession, RawJanssonValue, RawPluginResult};
+    use glib_sys::gboolean;
+    use std::os::raw::{c_char, c_int};
+    use std::ptr;
+
+    #[no_mangle]
+    pub static refcount_debug: c_int = 0;
+
+    #[no_mangle]
+    pub static janus_log_level: c_int = 0;
+
+    #[no_mangle]
+    pub static janus_log_timestamps: c_int = 0;
+
+    #[no_mangle]
+    pub static janus_log_colors: c_int = 0;
+
+    #[no_mangle]
+    pub extern "C" fn janus_vprintf(_format: *const c_char) {}
+
+    #[no_mangle]
+    pub extern "C" fn janus_plugin_result_new(
+        type_: PluginResultType,
+        text: *const c_char,
+        content: *mut RawJanssonValue,
+    ) -> *mut RawPluginResult {
+        Box::into_raw(Box::new(RawPluginResult { type_, text, content }))
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn janus_plugin_result_destroy(result: *mut RawPluginResult) {
+        drop(Box::from_raw(result));
+    }
+
+    // libjanus's SDP functions behave as if they always failed, so tests can check that failures are surfaced
+    mod sdp {
+        use crate::sdp::RawSdp;
+        use std::os::raw::c_char;
+        use std::ptr;
+
+        #[no_mangle]
+        pub extern "C" fn janus_sdp_parse(_sdp: *const c_char, _error: *mut c_char, _errlen: usize) -> *mut RawSdp {
+            ptr::null_mut()
+        }
+
+        #[no_mangle]
+        pub extern "C" fn janus_sdp_write(_sdp: *mut RawSdp) -> *mut c_char {
+            ptr::null_mut()
+        }
+
+        #[no_mangle]
+        pub extern "C" fn janus_sdp_destroy(_sdp: *mut RawSdp) {}
+
+        #[no_mangle]
+        pub extern "C" fn janus_sdp_generate_offer(_name: *const c_char, _address: *const c_char) -> *mut RawSdp {
+            ptr::null_mut()
+        }
+
+        #[no_mangle]
+        pub extern "C" fn janus_sdp_generate_answer(_offer: *mut RawSdp) -> *mut RawSdp {
+            ptr::null_mut()
+        }
+    }
+
+    unsafe extern "C" fn init(_callback: *mut PluginCallbacks, _config_path: *const c_char) -> c_int { 0 }
+    unsafe extern "C" fn destroy() {}
+    unsafe extern "C" fn get_int() -> c_int { 0 }
+    unsafe extern "C" fn get_str() -> *const c_char { b"stub\0".as_ptr() as *const c_char }
+    unsafe extern "C" fn session_error(_handle: *mut PluginSession, _error: *mut c_int) {}
+    unsafe extern "C" fn handle_message(
+        _handle: *mut PluginSession,
+        _transaction: *mut c_char,
+        _message: *mut RawJanssonValue,
+        _jsep: *mut RawJanssonValue,
+    ) -> *mut RawPluginResult {
+        ptr::null_mut()
+    }
+    unsafe extern "C" fn handle_admin_message(_message: *mut RawJanssonValue) -> *mut RawJanssonValue { ptr::null_mut() }
+    unsafe extern "C" fn session(_handle: *mut PluginSession) {}
+    unsafe extern "C" fn incoming_rtp(_handle: *mut PluginSession, _packet: *mut janus_plugin_rtp) {}
+    unsafe extern "C" fn incoming_rtcp(_handle: *mut PluginSession, _packet: *mut janus_plugin_rtcp) {}
+    unsafe extern "C" fn incoming_data(_handle: *mut PluginSession, _packet: *mut janus_plugin_data) {}
+    unsafe extern "C" fn slow_link(_handle: *mut PluginSession, _uplink: c_int, _video: c_int) {}
+    unsafe extern "C" fn query_session(_handle: *mut PluginSession) -> *mut RawJanssonValue { ptr::null_mut() }
+
+    /// A plugin which does nothing, for tests which need one to hand to the callbacks.
+    pub static PLUGIN: Plugin = Plugin {
+        init,
+        destroy,
+        get_api_compatibility: get_int,
+        get_version: get_int,
+        get_version_string: get_str,
+        get_description: get_str,
+        get_name: get_str,
+        get_author: get_str,
+        get_package: get_str,
+        create_session: session_error,
+        handle_message,
+        handle_admin_message,
+        setup_media: session,
+        incoming_rtp,
+        incoming_rtcp,
+        incoming_data,
+        data_ready: session,
+        slow_link,
+        hangup_media: session,
+        destroy_session: session_error,
+        query_session,
+    };
+
+    extern "C" fn push_event(
+        _handle: *mut PluginSession,
+        _plugin: *mut Plugin,
+        _transaction: *const c_char,
+        _message: *mut RawJanssonValue,
+        _jsep: *mut RawJanssonValue,
+    ) -> c_int {
+        0
+    }
+    extern "C" fn relay_rtp(_handle: *mut PluginSession, _packet: *mut janus_plugin_rtp) {}
+    extern "C" fn relay_rtcp(_handle: *mut PluginSession, _packet: *mut janus_plugin_rtcp) {}
+    extern "C" fn relay_data(_handle: *mut PluginSession, _packet: *mut janus_plugin_data) {}
+    extern "C" fn handle(_handle: *mut PluginSession) {}
+    extern "C" fn send_remb(_handle: *mut PluginSession, _bitrate: c_int) {}
+    extern "C" fn events_is_enabled() -> c_int { 0 }
+    extern "C" fn notify_event(_plugin: *mut Plugin, _handle: *mut PluginSession, _event: *mut RawJanssonValue) {}
+    extern "C" fn auth_is_signature_valid(_plugin: *mut Plugin, _token: *const c_char) -> gboolean { 0 }
+    extern "C" fn auth_signature_contains(_plugin: *mut Plugin, _token: *const c_char, _descriptor: *const c_char) -> gboolean { 0 }
+
+    /// Callbacks which do nothing, with events and every token disabled. Tests override the ones they care about and
+    /// leak the result to get the `&'static PluginCallbacks` which Janus would have passed to `init`.
+    pub fn callbacks() -> PluginCallbacks {
+        PluginCallbacks {
+            push_event,
+            relay_rtp,
+            relay_rtcp,
+            relay_data,
+            send_pli: handle,
+            send_remb,
+            close_pc: handle,
+            end_session: handle,
+            events_is_enabled,
+            notify_event,
+            auth_is_signature_valid,
+            auth_signature_contains,
+        }
+    }
+}
+
 bitflags! {
     /// Flags that control which events an event handler receives.
     pub struct JanusEventType: u32 {
@@ -46,20 +210,143 @@ bitflags! {
     }
 }
 
+/// A documented Janus API error code. See apierror.h in the Janus source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JanusErrorCode {
+    /// The request had a wrong or missing secret or token (403).
+    Unauthorized,
+    /// The token isn't allowed to access the plugin (405).
+    UnauthorizedPlugin,
+    /// An unknown error (490).
+    Unknown,
+    /// An error specific to the transport the request came in on (450).
+    TransportSpecific,
+    /// The request didn't say what it was (452).
+    MissingRequest,
+    /// The request was of an unknown kind (453).
+    UnknownRequest,
+    /// The request wasn't valid JSON (454).
+    InvalidJson,
+    /// The request wasn't a JSON object (455).
+    InvalidJsonObject,
+    /// The request was missing a mandatory element (456).
+    MissingMandatoryElement,
+    /// The request was sent to a path which doesn't handle it (457).
+    InvalidRequestPath,
+    /// The request referred to a session which doesn't exist (458).
+    SessionNotFound,
+    /// The request referred to a handle which doesn't exist (459).
+    HandleNotFound,
+    /// The request referred to a plugin which doesn't exist (460).
+    PluginNotFound,
+    /// Attaching a handle to a plugin failed (461).
+    PluginAttach,
+    /// Sending a message to a plugin failed (462).
+    PluginMessage,
+    /// Detaching a handle from a plugin failed (463).
+    PluginDetach,
+    /// The JSEP had a type other than offer or answer (464).
+    JsepUnknownType,
+    /// The JSEP's SDP couldn't be parsed (465).
+    JsepInvalidSdp,
+    /// A trickled candidate referred to a stream which doesn't exist (466).
+    TrickleInvalidStream,
+    /// An element of the request had the wrong type (467).
+    InvalidElementType,
+    /// A session was created with an ID which is already in use (468).
+    SessionConflict,
+    /// An answer was sent when no offer had been made (469).
+    UnexpectedAnswer,
+    /// The request's token wasn't found (470).
+    TokenNotFound,
+    /// The request isn't possible in the handle's current WebRTC state (471).
+    WebrtcState,
+    /// Janus is currently not accepting new sessions (472).
+    NotAcceptingSessions,
+    /// A code which isn't in the list of documented codes.
+    Other(UndocumentedErrorCode),
+}
+
+/// A numeric Janus API error code which isn't one of the documented ones. These are only made by
+/// `JanusErrorCode::from_code`, so a `JanusErrorCode::Other` never has the same code as one of the other variants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UndocumentedErrorCode(i32);
+
+impl UndocumentedErrorCode {
+    /// Returns the numeric value of this error code.
+    pub fn code(self) -> i32 {
+        self.0
+    }
+}
+
+/// Each documented error code, its numeric value, and Janus's description of it. See apierror.c.
+static JANUS_ERROR_CODES: [(JanusErrorCode, i32, &str); 25] = [
+    (JanusErrorCode::Unauthorized, 403, "Unauthorized request (wrong or missing secret/token)"),
+    (JanusErrorCode::UnauthorizedPlugin, 405, "Unauthorized access to plugin (token is not allowed to)"),
+    (JanusErrorCode::Unknown, 490, "Unknown error"),
+    (JanusErrorCode::TransportSpecific, 450, "Transport specific error"),
+    (JanusErrorCode::MissingRequest, 452, "Missing request"),
+    (JanusErrorCode::UnknownRequest, 453, "Unknown request"),
+    (JanusErrorCode::InvalidJson, 454, "Invalid JSON"),
+    (JanusErrorCode::InvalidJsonObject, 455, "Invalid JSON Object"),
+    (JanusErrorCode::MissingMandatoryElement, 456, "Missing mandatory element"),
+    (JanusErrorCode::InvalidRequestPath, 457, "Invalid path for this request"),
+    (JanusErrorCode::SessionNotFound, 458, "Session not found"),
+    (JanusErrorCode::HandleNotFound, 459, "Handle not found"),
+    (JanusErrorCode::PluginNotFound, 460, "Plugin not found"),
+    (JanusErrorCode::PluginAttach, 461, "Error attaching plugin"),
+    (JanusErrorCode::PluginMessage, 462, "Error sending message to plugin"),
+    (JanusErrorCode::PluginDetach, 463, "Error detaching from plugin"),
+    (JanusErrorCode::JsepUnknownType, 464, "Unsupported JSEP type"),
+    (JanusErrorCode::JsepInvalidSdp, 465, "Invalid SDP"),
+    (JanusErrorCode::TrickleInvalidStream, 466, "Invalid stream"),
+    (JanusErrorCode::InvalidElementType, 467, "Invalid element type"),
+    (JanusErrorCode::SessionConflict, 468, "Session ID already in use"),
+    (JanusErrorCode::UnexpectedAnswer, 469, "Unexpected ANSWER (no OFFER)"),
+    (JanusErrorCode::TokenNotFound, 470, "Token not found"),
+    (JanusErrorCode::WebrtcState, 471, "Wrong WebRTC state"),
+    (JanusErrorCode::NotAcceptingSessions, 472, "Currently not accepting new sessions"),
+];
+
+impl JanusErrorCode {
+    /// Looks up the error code with the given numeric value.
+    pub fn from_code(code: i32) -> Self {
+        JANUS_ERROR_CODES.iter().find(|(_, c, _)| *c == code).map_or(JanusErrorCode::Other(UndocumentedErrorCode(code)), |(e, _, _)| *e)
+    }
+
+    /// Returns the numeric value of this error code.
+    pub fn code(self) -> i32 {
+        match self {
+            JanusErrorCode::Other(code) => code.code(),
+            e => JANUS_ERROR_CODES.iter().find(|(x, _, _)| *x == e).map(|(_, c, _)| *c).unwrap(),
+        }
+    }
+
+    /// Returns Janus's description text for this error code.
+    pub fn description(self) -> &'static str {
+        JANUS_ERROR_CODES.iter().find(|(x, _, _)| *x == self).map_or("Unknown error", |(_, _, d)| d)
+    }
+}
+
 /// An error emitted by the Janus core in response to a plugin pushing an event.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct JanusError {
-    pub code: i32
+    pub code: i32,
 }
 
 /// A result from pushing an event to Janus core.
 pub type JanusResult = Result<(), JanusError>;
 
 impl JanusError {
-    /// Returns Janus's description text for this error.
+    /// Returns Janus's description text for this error, as reported by the Janus core.
     pub fn to_cstr(self) -> &'static CStr {
         unsafe { CStr::from_ptr(ffi::janus_get_api_error(self.code)) }
     }
+
+    /// Returns the documented error code matching this error's code, or `JanusErrorCode::Other` if there isn't one.
+    pub fn kind(self) -> JanusErrorCode {
+        JanusErrorCode::from_code(self.code)
+    }
     /// Converts a Janus result code to either success or a potential error.
     pub fn from(val: i32) -> JanusResult {
         match val {
@@ -73,8 +360,7 @@ impl Error for JanusError {}
 
 impl fmt::Display for JanusError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} (code: {})", self.to_cstr().to_str().unwrap()
-, self.code)
+        write!(f, "{} (code: {})", self.kind().description(), self.code)
     }
 }
 
@@ -216,3 +502,30 @@ macro_rules! export_eventhandler {
         pub extern "C" fn create() -> *const $crate::EventHandler { $evh }
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn error_codes_round_trip() {
+        for (e, code, _) in JANUS_ERROR_CODES.iter() {
+            assert_eq!(*e, JanusErrorCode::from_code(*code));
+            assert_eq!(*code, e.code());
+        }
+        let other = JanusErrorCode::from_code(12345);
+        assert!(matches!(other, JanusErrorCode::Other(code) if code.code() == 12345));
+        assert_eq!(12345, other.code());
+    }
+
+    #[test]
+    fn format_errors() {
+        assert_eq!(Ok(()), JanusError::from(0));
+        let err = JanusError::from(458).unwrap_err();
+        assert_eq!(458, err.code);
+        assert_eq!(JanusErrorCode::SessionNotFound, err.kind());
+        assert_eq!("Session not found (code: 458)", err.to_string());
+        assert_eq!("Unknown error (code: 12345)", JanusError::from(12345).unwrap_err().to_string());
+    }
+}
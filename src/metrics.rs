@@ -0,0 +1,483 @@
+/// A lightweight registry of plugin metrics (counters, gauges and histograms.) Metrics can be updated lock-free from
+/// media callbacks like `incoming_rtp`, and read via an admin request or in the Prometheus text format.
+use crate::admin::AdminRegistry;
+use serde::Serialize;
+use std::fmt::{self, Write as FmtWrite};
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+/// How often the metrics server checks whether it's been stopped while waiting for connections.
+const SERVER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long the metrics server waits for a client to read its metrics before giving up on it.
+const SERVER_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long stopping the metrics server waits for its thread to exit before leaving it behind.
+const SERVER_STOP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A value which only ever goes up, e.g. the number of packets relayed.
+#[derive(Debug, Default)]
+pub struct Counter {
+    value: AtomicU64,
+}
+
+impl Counter {
+    /// Increments the counter by 1.
+    pub fn inc(&self) {
+        self.add(1)
+    }
+
+    /// Increments the counter by the given amount.
+    pub fn add(&self, n: u64) {
+        self.value.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Returns the current value.
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+/// A value which can go up and down, e.g. the number of active sessions.
+#[derive(Debug, Default)]
+pub struct Gauge {
+    value: AtomicI64,
+}
+
+impl Gauge {
+    /// Sets the gauge to the given value.
+    pub fn set(&self, n: i64) {
+        self.value.store(n, Ordering::Relaxed);
+    }
+
+    /// Increments the gauge by 1.
+    pub fn inc(&self) {
+        self.add(1)
+    }
+
+    /// Decrements the gauge by 1.
+    pub fn dec(&self) {
+        self.add(-1)
+    }
+
+    /// Adds the given amount (which may be negative) to the gauge.
+    pub fn add(&self, n: i64) {
+        self.value.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Returns the current value.
+    pub fn get(&self) -> i64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+/// A distribution of observed values, e.g. message handling latency, counted in buckets with fixed upper bounds.
+#[derive(Debug)]
+pub struct Histogram {
+    bounds: Vec<f64>,
+    buckets: Vec<AtomicU64>, // one per bound, plus one for everything larger
+    count: AtomicU64,
+    sum_bits: AtomicU64, // an f64
+}
+
+impl Histogram {
+    /// Creates a histogram with buckets for values up to each of the given bounds, which are sorted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the bounds is NaN.
+    pub fn new(bounds: &[f64]) -> Self {
+        let mut bounds = bounds.to_vec();
+        bounds.sort_by(|a, b| a.partial_cmp(b).expect("NaN histogram bound"));
+        let buckets = (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect();
+        Self { bounds, buckets, count: AtomicU64::new(0), sum_bits: AtomicU64::new(0f64.to_bits()) }
+    }
+
+    /// Records an observed value.
+    pub fn observe(&self, value: f64) {
+        let bucket = self.bounds.iter().position(|&b| value <= b).unwrap_or(self.bounds.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let _ = self.sum_bits.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| Some((f64::from_bits(bits) + value).to_bits()));
+    }
+
+    /// Returns a snapshot of the current distribution.
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let mut cumulative = 0;
+        let buckets = self
+            .bounds
+            .iter()
+            .zip(&self.buckets)
+            .map(|(&le, n)| {
+                cumulative += n.load(Ordering::Relaxed);
+                Bucket { le, count: cumulative }
+            })
+            .collect();
+        HistogramSnapshot {
+            buckets,
+            count: self.count.load(Ordering::Relaxed),
+            sum: f64::from_bits(self.sum_bits.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// The number of observations less than or equal to a bucket's upper bound.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Bucket {
+    pub le: f64,
+    pub count: u64,
+}
+
+/// A snapshot of a histogram. Bucket counts are cumulative, and the total count includes values above every bound.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistogramSnapshot {
+    pub buckets: Vec<Bucket>,
+    pub count: u64,
+    pub sum: f64,
+}
+
+#[derive(Debug, Clone)]
+enum Metric {
+    Counter(Arc<Counter>),
+    Gauge(Arc<Gauge>),
+    Histogram(Arc<Histogram>),
+}
+
+#[derive(Debug)]
+struct Entry {
+    name: String,
+    help: String,
+    metric: Metric,
+}
+
+/// The current value of a single metric.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum MetricValue {
+    Counter { value: u64 },
+    Gauge { value: i64 },
+    Histogram(HistogramSnapshot),
+}
+
+/// A snapshot of a single metric.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricSnapshot {
+    pub name: String,
+    pub help: String,
+    #[serde(flatten)]
+    pub value: MetricValue,
+}
+
+/// The admin request which returns a snapshot of every metric.
+pub const METRICS_REQUEST: &str = "metrics";
+
+#[derive(Debug, Serialize)]
+struct MetricsResponse {
+    metrics: Vec<MetricSnapshot>,
+}
+
+/// A collection of named metrics. Registering metrics takes a lock, so plugins should register them up front and
+/// keep the returned handles around for updating.
+#[derive(Debug, Default)]
+pub struct MetricsRegistry {
+    entries: RwLock<Vec<Entry>>,
+}
+
+impl MetricsRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn register(&self, name: &str, help: &str, create: impl FnOnce() -> Metric) -> Metric {
+        let mut entries = self.entries.write().unwrap();
+        if let Some(entry) = entries.iter().find(|e| e.name == name) {
+            return entry.metric.clone();
+        }
+        let metric = create();
+        entries.push(Entry { name: name.to_owned(), help: help.to_owned(), metric: metric.clone() });
+        metric
+    }
+
+    /// Returns the counter with the given name, registering it if it doesn't exist yet.
+    ///
+    /// Panics if a different kind of metric was already registered with the name.
+    pub fn counter(&self, name: &str, help: &str) -> Arc<Counter> {
+        match self.register(name, help, || Metric::Counter(Arc::default())) {
+            Metric::Counter(c) => c,
+            _ => panic!("Metric {} is already registered, but not as a counter.", name),
+        }
+    }
+
+    /// Returns the gauge with the given name, registering it if it doesn't exist yet.
+    ///
+    /// Panics if a different kind of metric was already registered with the name.
+    pub fn gauge(&self, name: &str, help: &str) -> Arc<Gauge> {
+        match self.register(name, help, || Metric::Gauge(Arc::default())) {
+            Metric::Gauge(g) => g,
+            _ => panic!("Metric {} is already registered, but not as a gauge.", name),
+        }
+    }
+
+    /// Returns the histogram with the given name, registering it with the given bucket bounds if it doesn't exist yet.
+    ///
+    /// Panics if a different kind of metric was already registered with the name, or if any of the bounds is NaN.
+    pub fn histogram(&self, name: &str, help: &str, bounds: &[f64]) -> Arc<Histogram> {
+        match self.register(name, help, || Metric::Histogram(Arc::new(Histogram::new(bounds)))) {
+            Metric::Histogram(h) => h,
+            _ => panic!("Metric {} is already registered, but not as a histogram.", name),
+        }
+    }
+
+    /// Returns a snapshot of every metric, in registration order.
+    pub fn snapshot(&self) -> Vec<MetricSnapshot> {
+        self.entries
+            .read()
+            .unwrap()
+            .iter()
+            .map(|e| MetricSnapshot {
+                name: e.name.clone(),
+                help: e.help.clone(),
+                value: match e.metric {
+                    Metric::Counter(ref c) => MetricValue::Counter { value: c.get() },
+                    Metric::Gauge(ref g) => MetricValue::Gauge { value: g.get() },
+                    Metric::Histogram(ref h) => MetricValue::Histogram(h.snapshot()),
+                },
+            })
+            .collect()
+    }
+
+    /// Renders every metric in the Prometheus text exposition format.
+    pub fn render_prometheus(&self) -> String {
+        let mut output = String::new();
+        for m in self.snapshot() {
+            let kind = match m.value {
+                MetricValue::Counter { .. } => "counter",
+                MetricValue::Gauge { .. } => "gauge",
+                MetricValue::Histogram(_) => "histogram",
+            };
+            writeln!(output, "# HELP {} {}", m.name, m.help.replace('\\', "\\\\").replace('\n', "\\n")).unwrap();
+            writeln!(output, "# TYPE {} {}", m.name, kind).unwrap();
+            match m.value {
+                MetricValue::Counter { value } => writeln!(output, "{} {}", m.name, value).unwrap(),
+                MetricValue::Gauge { value } => writeln!(output, "{} {}", m.name, value).unwrap(),
+                MetricValue::Histogram(h) => {
+                    for b in &h.buckets {
+                        writeln!(output, "{}_bucket{{le=\"{}\"}} {}", m.name, b.le, b.count).unwrap();
+                    }
+                    writeln!(output, "{}_bucket{{le=\"+Inf\"}} {}", m.name, h.count).unwrap();
+                    writeln!(output, "{}_sum {}", m.name, h.sum).unwrap();
+                    writeln!(output, "{}_count {}", m.name, h.count).unwrap();
+                }
+            }
+        }
+        output
+    }
+
+    /// Registers the `metrics` admin command, which responds with a snapshot of every metric, i.e.
+    /// `{"result": "ok", "metrics": [...]}`.
+    pub fn register_admin_command<'a>(self: &Arc<Self>, registry: &'a mut AdminRegistry) -> &'a mut AdminRegistry {
+        let metrics = Arc::clone(self);
+        registry.register(METRICS_REQUEST, "Reports a snapshot of every metric.", move |_: serde_json::Value| {
+            Ok(MetricsResponse { metrics: metrics.snapshot() })
+        })
+    }
+
+    /// Writes every metric in the Prometheus text format to the given file, replacing it atomically so that
+    /// readers never see a partial write. Suitable for scheduling periodically, e.g. for the node exporter's
+    /// textfile collector.
+    pub fn write_prometheus_file(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, self.render_prometheus())?;
+        fs::rename(&tmp, path)
+    }
+
+    /// Starts a thread which listens on a Unix socket at the given path, and writes every metric in the
+    /// Prometheus text format to each client which connects. A socket left at the path by a previous run is replaced,
+    /// but anything else there is an error.
+    pub fn serve_prometheus(self: &Arc<Self>, path: impl Into<PathBuf>) -> io::Result<PrometheusServer> {
+        let path = path.into();
+        remove_stale_socket(&path)?;
+        let listener = UnixListener::bind(&path)?;
+        listener.set_nonblocking(true)?;
+        let stopped = Arc::new(AtomicBool::new(false));
+        let registry = Arc::clone(self);
+        let stop_signal = Arc::clone(&stopped);
+        let (exited_tx, exited) = mpsc::channel::<()>();
+        thread::Builder::new().name("metrics server".into()).spawn(move || {
+            let _exited_tx = exited_tx; // dropped when the thread exits
+            while !stop_signal.load(Ordering::SeqCst) {
+                let result = match listener.accept() {
+                    Ok((mut stream, _)) => stream
+                        .set_nonblocking(false)
+                        .and_then(|_| stream.set_write_timeout(Some(SERVER_WRITE_TIMEOUT)))
+                        .and_then(|_| stream.write_all(registry.render_prometheus().as_bytes())),
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        thread::sleep(SERVER_POLL_INTERVAL);
+                        Ok(())
+                    }
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    crate::janus_warn!("Error serving metrics: {}", e);
+                }
+            }
+        })?;
+        Ok(PrometheusServer { path, stopped, exited: Some(exited) })
+    }
+}
+
+// removes whatever's at the path if it's a socket, i.e. one left behind by a previous run which didn't exit cleanly
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(ref metadata) if metadata.file_type().is_socket() => fs::remove_file(path),
+        Ok(_) => Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} exists and isn't a socket", path.display()))),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// A thread serving metrics over a Unix socket. The thread is stopped and the socket removed when the server is
+/// stopped or dropped.
+pub struct PrometheusServer {
+    path: PathBuf,
+    stopped: Arc<AtomicBool>,
+    exited: Option<Receiver<()>>,
+}
+
+impl PrometheusServer {
+    /// Returns the path of the socket being served.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Stops serving and waits for the server thread to exit. If it doesn't exit within a couple of seconds (e.g. because
+    /// it's stuck rendering metrics), it's left to exit on its own.
+    pub fn stop(mut self) {
+        self.shut_down();
+    }
+
+    fn shut_down(&mut self) {
+        if let Some(exited) = self.exited.take() {
+            self.stopped.store(true, Ordering::SeqCst);
+            if let Err(RecvTimeoutError::Timeout) = exited.recv_timeout(SERVER_STOP_TIMEOUT) {
+                crate::janus_warn!("Timed out waiting for the metrics server to stop.");
+            }
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+impl Drop for PrometheusServer {
+    fn drop(&mut self) {
+        self.shut_down();
+    }
+}
+
+impl fmt::Debug for PrometheusServer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PrometheusServer {{ path: {:?} }}", self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::Read;
+    use std::os::unix::net::UnixStream;
+
+    fn example_registry() -> MetricsRegistry {
+        let registry = MetricsRegistry::new();
+        registry.counter("packets_relayed", "Packets relayed.").add(3);
+        let sessions = registry.gauge("active_sessions", "Active sessions.");
+        sessions.inc();
+        sessions.inc();
+        sessions.dec();
+        let latency = registry.histogram("message_latency_seconds", "Message latency.", &[0.5, 0.1]);
+        latency.observe(0.05);
+        latency.observe(0.2);
+        latency.observe(2.0);
+        registry
+    }
+
+    #[test]
+    fn reuse_registered_metrics() {
+        let registry = example_registry();
+        registry.counter("packets_relayed", "Packets relayed.").inc();
+        assert_eq!(registry.counter("packets_relayed", "").get(), 4);
+        assert_eq!(registry.snapshot().len(), 3);
+    }
+
+    #[test]
+    fn render_prometheus_text() {
+        assert_eq!(
+            example_registry().render_prometheus(),
+            "# HELP packets_relayed Packets relayed.\n\
+             # TYPE packets_relayed counter\n\
+             packets_relayed 3\n\
+             # HELP active_sessions Active sessions.\n\
+             # TYPE active_sessions gauge\n\
+             active_sessions 1\n\
+             # HELP message_latency_seconds Message latency.\n\
+             # TYPE message_latency_seconds histogram\n\
+             message_latency_seconds_bucket{le=\"0.1\"} 1\n\
+             message_latency_seconds_bucket{le=\"0.5\"} 2\n\
+             message_latency_seconds_bucket{le=\"+Inf\"} 3\n\
+             message_latency_seconds_sum 2.25\n\
+             message_latency_seconds_count 3\n"
+        );
+    }
+
+    #[test]
+    fn respond_to_admin_requests() {
+        let mut admin = AdminRegistry::new();
+        Arc::new(example_registry()).register_admin_command(&mut admin);
+        let request = crate::jansson::JanssonValue::from_serde(&serde_json::json!({ "request": "metrics" })).unwrap();
+        let response = admin.dispatch(&request).to_serde::<serde_json::Value>().unwrap();
+        assert_eq!(response["result"], "ok");
+        assert_eq!(response["metrics"][0], serde_json::json!({ "name": "packets_relayed", "help": "Packets relayed.", "type": "counter", "value": 3 }));
+        assert_eq!(response["metrics"][2]["count"], 3);
+    }
+
+    #[test]
+    fn serve_over_unix_socket() {
+        let registry = Arc::new(example_registry());
+        let path = std::env::temp_dir().join(format!("janus-plugin-metrics-{}.sock", std::process::id()));
+        let server = registry.serve_prometheus(&path).unwrap();
+        let mut output = String::new();
+        UnixStream::connect(&path).unwrap().read_to_string(&mut output).unwrap();
+        assert_eq!(output, registry.render_prometheus());
+        server.stop();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn only_replace_sockets() {
+        let registry = Arc::new(example_registry());
+        let path = std::env::temp_dir().join(format!("janus-plugin-metrics-stale-{}.sock", std::process::id()));
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+        let server = registry.serve_prometheus(&path).unwrap();
+        let mut output = String::new();
+        UnixStream::connect(&path).unwrap().read_to_string(&mut output).unwrap();
+        assert_eq!(output, registry.render_prometheus());
+        server.stop();
+
+        fs::write(&path, "not a socket").unwrap();
+        let err = registry.serve_prometheus(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "not a socket");
+        fs::remove_file(&path).unwrap();
+    }
+}
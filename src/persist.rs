@@ -0,0 +1,331 @@
+/// Utilities to persist plugin-wide state (e.g. room configuration) to disk, so that it survives Janus restarts.
+use crate::admin::AdminRegistry;
+use crate::scheduler::{Scheduler, TaskHandle};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
+use std::time::Duration;
+
+/// An error indicating that we failed to save or restore persisted state.
+#[derive(Debug)]
+pub enum PersistError {
+    /// The state file couldn't be read or written.
+    Io(io::Error),
+    /// The state file couldn't be parsed, or the state couldn't be serialized.
+    Format(serde_json::Error),
+    /// The state file was written with a newer schema version than this plugin understands.
+    UnknownVersion { found: u32, expected: u32 },
+    /// The state file was written with an older schema version and couldn't be migrated.
+    Migration(String),
+}
+
+impl Error for PersistError {}
+
+impl fmt::Display for PersistError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PersistError::Io(e) => write!(f, "Error accessing state file: {}", e),
+            PersistError::Format(e) => write!(f, "Error encoding state: {}", e),
+            PersistError::UnknownVersion { found, expected } => {
+                write!(f, "State file has schema version {}, but only versions up to {} are supported.", found, expected)
+            }
+            PersistError::Migration(e) => write!(f, "Error migrating state: {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for PersistError {
+    fn from(e: io::Error) -> Self {
+        PersistError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for PersistError {
+    fn from(e: serde_json::Error) -> Self {
+        PersistError::Format(e)
+    }
+}
+
+/// The contents of a state file: the state, tagged with the schema version it was written with.
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope<T> {
+    version: u32,
+    state: T,
+}
+
+type Migration = Box<dyn Fn(u32, serde_json::Value) -> Result<serde_json::Value, String> + Send + Sync>;
+
+/// A versioned JSON file holding a snapshot of some state.
+pub struct StateFile {
+    path: PathBuf,
+    version: u32,
+    migrate: Option<Migration>,
+    // held while saving, since every save goes through the same temporary file
+    saving: Mutex<()>,
+}
+
+impl StateFile {
+    /// Describes a state file named `<name>.json` in the given directory, whose state has the given schema version.
+    /// Bump the version whenever the state's serialized form changes incompatibly.
+    pub fn new(dir: impl AsRef<Path>, name: &str, version: u32) -> Self {
+        Self { path: dir.as_ref().join(format!("{}.json", name)), version, migrate: None, saving: Mutex::new(()) }
+    }
+
+    /// Sets a function which upgrades state written with an older schema version. It's called with the old version
+    /// and the old state as JSON, and should return the state as JSON in the current version's form.
+    pub fn migrate_with<F>(mut self, f: F) -> Self
+    where
+        F: Fn(u32, serde_json::Value) -> Result<serde_json::Value, String> + Send + Sync + 'static,
+    {
+        self.migrate = Some(Box::new(f));
+        self
+    }
+
+    /// Returns the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the state from the file, migrating it if it was written with an older schema version. Returns None if
+    /// the file doesn't exist.
+    pub fn load<S: DeserializeOwned>(&self) -> Result<Option<S>, PersistError> {
+        let contents = match fs::read(&self.path) {
+            Ok(contents) => contents,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let envelope: Envelope<serde_json::Value> = serde_json::from_slice(&contents)?;
+        let state = if envelope.version == self.version {
+            envelope.state
+        } else if envelope.version < self.version {
+            match self.migrate {
+                Some(ref migrate) => migrate(envelope.version, envelope.state).map_err(PersistError::Migration)?,
+                None => return Err(PersistError::Migration(format!("no migration from version {}", envelope.version))),
+            }
+        } else {
+            return Err(PersistError::UnknownVersion { found: envelope.version, expected: self.version });
+        };
+        Ok(Some(serde_json::from_value(state)?))
+    }
+
+    /// Writes the state to the file atomically, so that a crash mid-write never leaves a partial file behind.
+    /// Concurrent saves through the same `StateFile` are written one at a time.
+    pub fn save<S: Serialize>(&self, state: &S) -> Result<(), PersistError> {
+        let contents = serde_json::to_vec_pretty(&Envelope { version: self.version, state })?;
+        let _saving = self.saving.lock().unwrap();
+        let mut tmp = self.path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&contents)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        if let Some(dir) = self.path.parent() {
+            // make sure the rename itself is durable
+            File::open(dir)?.sync_all()?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for StateFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "StateFile {{ path: {:?}, version: {} }}", self.path, self.version)
+    }
+}
+
+/// When persisted state is written to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SavePolicy {
+    /// After every update.
+    OnChange,
+    /// Periodically, if anything changed, once `schedule` has been called.
+    Periodic(Duration),
+    /// Only when `save` is called or a `save_state` admin request arrives.
+    Manual,
+}
+
+/// The admin request which forces a snapshot of the state to disk.
+pub const SAVE_STATE_REQUEST: &str = "save_state";
+
+#[derive(Debug, Serialize)]
+struct SaveResponse {
+    path: String,
+}
+
+/// Plugin-wide state which is restored from a state file when it's opened (i.e. in `init`) and saved back to the
+/// file according to a `SavePolicy`.
+#[derive(Debug)]
+pub struct PersistentState<S> {
+    file: StateFile,
+    policy: SavePolicy,
+    state: RwLock<S>,
+    dirty: AtomicBool,
+}
+
+impl<S: Serialize + DeserializeOwned> PersistentState<S> {
+    /// Restores the state from the given file, or uses the given default if the file doesn't exist yet.
+    pub fn open(file: StateFile, policy: SavePolicy, default: impl FnOnce() -> S) -> Result<Self, PersistError> {
+        let state = file.load()?.unwrap_or_else(default);
+        Ok(Self { file, policy, state: RwLock::new(state), dirty: AtomicBool::new(false) })
+    }
+
+    /// Returns the current state.
+    pub fn read(&self) -> RwLockReadGuard<'_, S> {
+        self.state.read().unwrap()
+    }
+
+    /// Changes the state. If the save policy is `OnChange`, the state is saved right away, and any error is logged.
+    pub fn update<R>(&self, f: impl FnOnce(&mut S) -> R) -> R {
+        let result = f(&mut self.state.write().unwrap());
+        self.dirty.store(true, Ordering::SeqCst);
+        if self.policy == SavePolicy::OnChange {
+            if let Err(e) = self.save() {
+                crate::janus_err!("Error saving state to {}: {}", self.file.path().display(), e);
+            }
+        }
+        result
+    }
+
+    /// Writes the current state to disk.
+    pub fn save(&self) -> Result<(), PersistError> {
+        let state = self.state.read().unwrap();
+        self.dirty.store(false, Ordering::SeqCst);
+        let result = self.file.save(&*state);
+        if result.is_err() {
+            self.dirty.store(true, Ordering::SeqCst);
+        }
+        result
+    }
+
+    /// Writes the current state to disk if it changed since it was last written. Returns whether it was written.
+    pub fn save_if_dirty(&self) -> Result<bool, PersistError> {
+        if self.dirty.load(Ordering::SeqCst) {
+            self.save().map(|_| true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// If the save policy is `Periodic`, schedules the state to be saved periodically whenever it changed.
+    pub fn schedule(self: &Arc<Self>, scheduler: &Scheduler) -> Option<TaskHandle>
+    where
+        S: Send + Sync + 'static,
+    {
+        match self.policy {
+            SavePolicy::Periodic(interval) => {
+                let state = Arc::clone(self);
+                Some(scheduler.schedule_repeating(interval, move || {
+                    if let Err(e) = state.save_if_dirty() {
+                        crate::janus_err!("Error saving state to {}: {}", state.file.path().display(), e);
+                    }
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    /// Registers the `save_state` admin command, which saves the state and responds with
+    /// `{"result": "ok", "path": "..."}`, or with the error if it couldn't be saved.
+    pub fn register_admin_command<'a>(self: &Arc<Self>, registry: &'a mut AdminRegistry) -> &'a mut AdminRegistry
+    where
+        S: Send + Sync + 'static,
+    {
+        let state = Arc::clone(self);
+        registry.register(SAVE_STATE_REQUEST, "Saves the plugin's state to disk.", move |_: serde_json::Value| {
+            state.save().map_err(|e| e.to_string())?;
+            Ok(SaveResponse { path: state.file.path().display().to_string() })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Rooms {
+        rooms: Vec<String>,
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("janus-plugin-persist-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn restore_saved_state() {
+        let dir = temp_dir("restore");
+        let state = PersistentState::open(StateFile::new(&dir, "rooms", 1), SavePolicy::OnChange, Rooms::default).unwrap();
+        assert_eq!(*state.read(), Rooms::default());
+        state.update(|s| s.rooms.push("lobby".into()));
+        assert!(!state.save_if_dirty().unwrap());
+
+        let restored = PersistentState::<Rooms>::open(StateFile::new(&dir, "rooms", 1), SavePolicy::Manual, || unreachable!()).unwrap();
+        assert_eq!(restored.read().rooms, vec!["lobby".to_owned()]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn migrate_old_versions() {
+        let dir = temp_dir("migrate");
+        StateFile::new(&dir, "rooms", 1).save(&vec!["lobby"]).unwrap();
+        assert!(StateFile::new(&dir, "rooms", 2).load::<Rooms>().is_err());
+        let file = StateFile::new(&dir, "rooms", 2).migrate_with(|version, old| {
+            assert_eq!(version, 1);
+            Ok(serde_json::json!({ "rooms": old }))
+        });
+        assert_eq!(file.load::<Rooms>().unwrap().unwrap().rooms, vec!["lobby".to_owned()]);
+        match StateFile::new(&dir, "rooms", 0).load::<Rooms>() {
+            Err(PersistError::UnknownVersion { found: 1, expected: 0 }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn save_on_admin_request() {
+        let dir = temp_dir("admin");
+        let state = Arc::new(PersistentState::open(StateFile::new(&dir, "rooms", 1), SavePolicy::Manual, Rooms::default).unwrap());
+        state.update(|s| s.rooms.push("lobby".into()));
+        assert!(!dir.join("rooms.json").exists());
+        let mut registry = AdminRegistry::new();
+        state.register_admin_command(&mut registry);
+        let request = crate::jansson::JanssonValue::from_serde(&serde_json::json!({ "request": "save_state" })).unwrap();
+        let response = registry.dispatch(&request).to_serde::<serde_json::Value>().unwrap();
+        assert_eq!(response, serde_json::json!({ "result": "ok", "path": dir.join("rooms.json").display().to_string() }));
+        assert_eq!(StateFile::new(&dir, "rooms", 1).load::<Rooms>().unwrap().unwrap().rooms, vec!["lobby".to_owned()]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn save_concurrently() {
+        let dir = temp_dir("concurrent");
+        let rooms = || Rooms { rooms: (0..1000).map(|i| format!("room {}", i)).collect() };
+        let state = Arc::new(PersistentState::open(StateFile::new(&dir, "rooms", 1), SavePolicy::Manual, rooms).unwrap());
+        let threads = (0..8)
+            .map(|_| {
+                let state = Arc::clone(&state);
+                std::thread::spawn(move || {
+                    for _ in 0..20 {
+                        state.save().unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(StateFile::new(&dir, "rooms", 1).load::<Rooms>().unwrap().unwrap(), rooms());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,259 @@
+/// Token-bucket rate limiting for signalling messages, so that one misbehaving client can't flood `handle_message`
+/// and starve every other session.
+use crate::session::{self, QuerySession, SessionWrapper};
+use crate::{PluginResult, PluginSession, RawJanssonValue};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// The error text sent back to clients whose requests are rejected by the limiter.
+fn rate_limited_text() -> &'static CStr {
+    CStr::from_bytes_with_nul(b"Too many requests; slow down and try again later.\0").unwrap()
+}
+
+/// A sustained rate of requests, plus how many requests may arrive in a burst above that rate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    /// Requests per second allowed over the long run.
+    pub per_second: f64,
+    /// How many requests may be made at once after a quiet period.
+    pub burst: u32,
+}
+
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        Self { limit, tokens: f64::from(limit.burst), refilled: now }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.per_second).min(f64::from(self.limit.burst));
+        self.refilled = now;
+    }
+
+    fn try_take(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn give_back(&mut self) {
+        self.tokens = (self.tokens + 1.0).min(f64::from(self.limit.burst));
+    }
+}
+
+/// Counts of the requests which a limiter let through and turned away.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize)]
+pub struct RateLimitStats {
+    pub allowed: u64,
+    pub rejected: u64,
+}
+
+impl RateLimitStats {
+    fn record(&mut self, allowed: bool) {
+        if allowed {
+            self.allowed += 1;
+        } else {
+            self.rejected += 1;
+        }
+    }
+}
+
+/// A session's `query_session` snapshot, with the session's rate limiting counters added as a `rate_limit` field.
+#[derive(Debug, Clone, Serialize)]
+pub struct RateLimitedSnapshot<S> {
+    #[serde(flatten)]
+    pub snapshot: S,
+    pub rate_limit: RateLimitStats,
+}
+
+#[derive(Debug)]
+struct SessionLimit {
+    bucket: Option<TokenBucket>,
+    stats: RateLimitStats,
+}
+
+#[derive(Debug)]
+struct GlobalLimit {
+    bucket: Option<TokenBucket>,
+    stats: RateLimitStats,
+}
+
+/// Limits the rate of requests both per session, keyed by Janus handle, and across the whole plugin. Plugins should
+/// call `check` at the top of `handle_message`, and `remove_session` from `destroy_session`. A session's counters can
+/// be included in its `query_session` response by answering it with `query_session`.
+#[derive(Debug)]
+pub struct RateLimiter {
+    per_session: Option<RateLimit>,
+    global: Mutex<GlobalLimit>,
+    sessions: Mutex<HashMap<usize, SessionLimit>>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter with an optional limit for each session, and an optional limit for the plugin as a whole.
+    pub fn new(per_session: Option<RateLimit>, global: Option<RateLimit>) -> Self {
+        let now = Instant::now();
+        Self {
+            per_session,
+            global: Mutex::new(GlobalLimit { bucket: global.map(|l| TokenBucket::new(l, now)), stats: RateLimitStats::default() }),
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns whether a request from the given session is allowed right now, consuming capacity if so.
+    pub fn try_acquire(&self, handle: *mut PluginSession) -> bool {
+        self.try_acquire_at(handle, Instant::now())
+    }
+
+    fn try_acquire_at(&self, handle: *mut PluginSession, now: Instant) -> bool {
+        let mut sessions = self.sessions.lock().unwrap();
+        let per_session = self.per_session;
+        let session = sessions.entry(handle as usize).or_insert_with(|| SessionLimit {
+            bucket: per_session.map(|l| TokenBucket::new(l, now)),
+            stats: RateLimitStats::default(),
+        });
+        let session_allowed = match session.bucket.as_mut() {
+            Some(b) => b.try_take(now),
+            None => true,
+        };
+        let mut global = self.global.lock().unwrap();
+        let allowed = session_allowed
+            && match global.bucket.as_mut() {
+                Some(b) => b.try_take(now),
+                None => true,
+            };
+        if session_allowed && !allowed {
+            // the plugin as a whole is too busy; don't charge the session for a request we didn't serve
+            if let Some(b) = session.bucket.as_mut() {
+                b.give_back();
+            }
+        }
+        session.stats.record(allowed);
+        global.stats.record(allowed);
+        allowed
+    }
+
+    /// Checks whether a request from the given session is allowed, returning the standard rate-limited error result
+    /// for the plugin to hand back to Janus if it isn't.
+    pub fn check(&self, handle: *mut PluginSession) -> Result<(), PluginResult> {
+        if self.try_acquire(handle) {
+            Ok(())
+        } else {
+            crate::janus_verb!("Rate limiting request from session {:p}.", handle);
+            Err(PluginResult::error(rate_limited_text()))
+        }
+    }
+
+    /// Returns the counters for the given session.
+    pub fn session_stats(&self, handle: *mut PluginSession) -> RateLimitStats {
+        self.sessions.lock().unwrap().get(&(handle as usize)).map(|s| s.stats).unwrap_or_default()
+    }
+
+    /// Returns the counters for the plugin as a whole.
+    pub fn global_stats(&self) -> RateLimitStats {
+        self.global.lock().unwrap().stats
+    }
+
+    /// Takes the given session's `query_session` snapshot, with its counters added.
+    pub fn snapshot<T: QuerySession>(&self, session: &SessionWrapper<T>) -> RateLimitedSnapshot<T::Snapshot> {
+        RateLimitedSnapshot { snapshot: session.snapshot(), rate_limit: self.session_stats(session.as_ptr()) }
+    }
+
+    /// Responds to a Janus `query_session` call like `session::query_session`, but with the session's counters added
+    /// to its snapshot, which should serialize to a JSON object. Call it from the plugin's `query_session` callback:
+    ///
+    /// ```ignore
+    /// extern "C" fn query_session(handle: *mut PluginSession) -> *mut RawJanssonValue {
+    ///     unsafe { RATE_LIMITER.query_session::<State>(handle) }
+    /// }
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The handle must be null or a Janus plugin session whose state, if any, was associated as a `T`.
+    pub unsafe fn query_session<T: QuerySession>(&self, handle: *mut PluginSession) -> *mut RawJanssonValue {
+        session::respond_to_query::<T, _>(handle, |session| self.snapshot(session))
+    }
+
+    /// Forgets about the given session, which should be called when it's destroyed.
+    pub fn remove_session(&self, handle: *mut PluginSession) {
+        self.sessions.lock().unwrap().remove(&(handle as usize));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::jansson::JanssonValue;
+    use crate::session::test_handle;
+    use std::time::Duration;
+
+    #[test]
+    fn limit_each_session() {
+        let limiter = RateLimiter::new(Some(RateLimit { per_second: 2.0, burst: 2 }), None);
+        let (a, b) = (0x1000 as *mut PluginSession, 0x2000 as *mut PluginSession);
+        let start = Instant::now();
+        assert!(limiter.try_acquire_at(a, start));
+        assert!(limiter.try_acquire_at(a, start));
+        assert!(!limiter.try_acquire_at(a, start));
+        assert!(limiter.try_acquire_at(b, start));
+        assert!(limiter.try_acquire_at(a, start + Duration::from_millis(500)));
+        assert_eq!(limiter.session_stats(a), RateLimitStats { allowed: 3, rejected: 1 });
+        assert_eq!(limiter.global_stats(), RateLimitStats { allowed: 4, rejected: 1 });
+        limiter.remove_session(a);
+        assert_eq!(limiter.session_stats(a), RateLimitStats::default());
+    }
+
+    #[test]
+    fn limit_the_plugin() {
+        let limiter = RateLimiter::new(Some(RateLimit { per_second: 1.0, burst: 1 }), Some(RateLimit { per_second: 1.0, burst: 1 }));
+        let (a, b) = (0x1000 as *mut PluginSession, 0x2000 as *mut PluginSession);
+        let start = Instant::now();
+        assert!(limiter.try_acquire_at(a, start));
+        assert!(!limiter.try_acquire_at(b, start));
+        // b wasn't charged for the rejected request, so it can go as soon as the plugin has capacity again
+        assert!(limiter.try_acquire_at(b, start + Duration::from_secs(1)));
+        assert_eq!(limiter.session_stats(b), RateLimitStats { allowed: 1, rejected: 1 });
+    }
+
+    #[test]
+    fn add_stats_to_query_session() {
+        #[derive(Serialize)]
+        struct Snapshot {
+            room: u32,
+        }
+        struct State(u32);
+        impl QuerySession for State {
+            type Snapshot = Snapshot;
+            fn snapshot(&self) -> Snapshot {
+                Snapshot { room: self.0 }
+            }
+        }
+
+        let limiter = RateLimiter::new(Some(RateLimit { per_second: 1.0, burst: 1 }), None);
+        let mut handle = test_handle();
+        assert!(unsafe { limiter.query_session::<State>(&mut handle) }.is_null());
+        let _session = unsafe { SessionWrapper::associate(&mut handle, State(1234)).unwrap() };
+        assert!(limiter.try_acquire(&mut handle));
+        assert!(!limiter.try_acquire(&mut handle));
+        let json = unsafe { JanssonValue::from_raw(limiter.query_session::<State>(&mut handle)).unwrap() };
+        assert_eq!(
+            json.to_serde::<serde_json::Value>().unwrap(),
+            serde_json::json!({ "room": 1234, "rate_limit": { "allowed": 1, "rejected": 1 } })
+        );
+    }
+}
@@ -1,31 +1,217 @@
 /// Utilities for working with Janus reference counts.
 use glib_sys;
 use janus_plugin_sys as ffi;
+use std::collections::BTreeMap;
 use std::ffi::CString;
+use std::fmt;
+use std::panic::Location;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
 pub use ffi::janus_refcount as ReferenceCount;
 
-/// Atomically increment the given reference count by 1.
-pub fn increase(refcount: &ReferenceCount) {
+/// Outstanding guards, keyed by guard ID, recorded while Janus refcount debugging is enabled.
+static ACQUISITIONS: Mutex<BTreeMap<u64, Acquisition>> = Mutex::new(BTreeMap::new());
+
+/// The source of unique IDs for recorded guards.
+static NEXT_GUARD_ID: AtomicU64 = AtomicU64::new(0);
+
+fn debug_enabled() -> bool {
+    unsafe { ffi::refcount_debug == 1 }
+}
+
+fn debug_print(msg: String) {
+    let msg = CString::new(msg).unwrap();
+    unsafe { ffi::janus_vprintf(msg.as_ptr()) }
+}
+
+fn incref(refcount: &ReferenceCount, location: Option<&Location>) {
     let field = &refcount.count;
     unsafe {
-        if ffi::refcount_debug == 1 {
-            let msg = CString::new(format!("[rust:increase] {:p} ({:?})\n", refcount, field + 1)).unwrap();
-            ffi::janus_vprintf(msg.as_ptr());
+        if debug_enabled() {
+            match location {
+                Some(loc) => debug_print(format!("[rust:increase] {:p} ({:?}) at {}\n", refcount, field + 1, loc)),
+                None => debug_print(format!("[rust:increase] {:p} ({:?})\n", refcount, field + 1)),
+            }
         }
         glib_sys::g_atomic_int_inc(field as *const _ as *mut _);
     }
 }
 
-/// Atomically decrement the given reference count by 1. If it's 0, call free.
-pub fn decrease(refcount: &ReferenceCount) {
+fn decref(refcount: &ReferenceCount, location: Option<&Location>) {
     let field = &refcount.count;
     unsafe {
-        if ffi::refcount_debug == 1 {
-            let msg = CString::new(format!("[rust:decrease] {:p} ({:?})\n", refcount, field - 1)).unwrap();
-            ffi::janus_vprintf(msg.as_ptr());
+        if debug_enabled() {
+            match location {
+                Some(loc) => debug_print(format!("[rust:decrease] {:p} ({:?}) acquired at {}\n", refcount, field - 1, loc)),
+                None => debug_print(format!("[rust:decrease] {:p} ({:?})\n", refcount, field - 1)),
+            }
         }
         if glib_sys::g_atomic_int_dec_and_test(field as *const _ as *mut _) == 1 {
             (refcount.free)(refcount);
         }
     }
 }
+
+/// Atomically increment the given reference count by 1.
+#[deprecated(note = "use a RefGuard to hold references instead")]
+pub fn increase(refcount: &ReferenceCount) {
+    incref(refcount, None)
+}
+
+/// Atomically decrement the given reference count by 1. If it's 0, call free.
+#[deprecated(note = "use a RefGuard to hold references instead")]
+pub fn decrease(refcount: &ReferenceCount) {
+    decref(refcount, None)
+}
+
+/// Information about where a still-outstanding `RefGuard` was acquired.
+#[derive(Debug, Clone, Copy)]
+pub struct Acquisition {
+    /// The address of the reference count the guard holds a reference on.
+    pub refcount: *const ReferenceCount,
+    /// The source location which created the guard.
+    pub location: &'static Location<'static>,
+}
+
+unsafe impl Send for Acquisition {}
+
+/// Returns every guard acquired while Janus refcount debugging was enabled which hasn't been dropped yet.
+pub fn outstanding() -> Vec<Acquisition> {
+    ACQUISITIONS.lock().unwrap().values().copied().collect()
+}
+
+/// Prints every outstanding guard to the Janus log, along with where it was acquired. Useful to call
+/// from a plugin's `destroy` to find references which were never released. Returns the number of leaks.
+pub fn report_leaks() -> usize {
+    let leaks = outstanding();
+    for leak in &leaks {
+        debug_print(format!("[rust:leak] {:p} acquired at {}\n", leak.refcount, leak.location));
+    }
+    leaks.len()
+}
+
+/// A reference on a Janus reference-counted object. The reference is released when the guard is dropped,
+/// and cloning the guard acquires another reference.
+///
+/// When Janus refcount debugging is enabled, each guard records the source location which acquired it,
+/// so that leaked references can be found with `outstanding` or `report_leaks`.
+pub struct RefGuard {
+    refcount: NonNull<ReferenceCount>,
+    location: &'static Location<'static>,
+    debug_id: Option<u64>,
+}
+
+impl RefGuard {
+    /// Acquires a new reference on the given reference count.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be null or point to a live Janus reference count, which must stay allocated
+    /// for as long as it has references.
+    #[track_caller]
+    pub unsafe fn new(refcount: *const ReferenceCount) -> Option<Self> {
+        let refcount = NonNull::new(refcount as *mut ReferenceCount)?;
+        let location = Location::caller();
+        incref(refcount.as_ref(), Some(location));
+        Some(Self::track(refcount, location))
+    }
+
+    /// Takes ownership of a reference which was already acquired on our behalf, e.g. the reference
+    /// Janus takes on a plugin session before handing it to `create_session`. Doesn't increment the count.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be null or point to a live Janus reference count, and the caller must own one of
+    /// its references, which is transferred to the guard.
+    #[track_caller]
+    pub unsafe fn adopt(refcount: *const ReferenceCount) -> Option<Self> {
+        let refcount = NonNull::new(refcount as *mut ReferenceCount)?;
+        Some(Self::track(refcount, Location::caller()))
+    }
+
+    fn track(refcount: NonNull<ReferenceCount>, location: &'static Location<'static>) -> Self {
+        let debug_id = if debug_enabled() {
+            let id = NEXT_GUARD_ID.fetch_add(1, Ordering::Relaxed);
+            let acquisition = Acquisition { refcount: refcount.as_ptr(), location };
+            ACQUISITIONS.lock().unwrap().insert(id, acquisition);
+            Some(id)
+        } else {
+            None
+        };
+        Self { refcount, location, debug_id }
+    }
+
+    /// Returns the reference count this guard holds a reference on.
+    pub fn as_ptr(&self) -> *const ReferenceCount {
+        self.refcount.as_ptr()
+    }
+
+    /// Returns the source location which acquired this guard.
+    pub fn location(&self) -> &'static Location<'static> {
+        self.location
+    }
+}
+
+impl Clone for RefGuard {
+    #[track_caller]
+    fn clone(&self) -> Self {
+        let location = Location::caller();
+        unsafe { incref(self.refcount.as_ref(), Some(location)) };
+        Self::track(self.refcount, location)
+    }
+}
+
+impl Drop for RefGuard {
+    fn drop(&mut self) {
+        if let Some(id) = self.debug_id {
+            ACQUISITIONS.lock().unwrap().remove(&id);
+        }
+        unsafe { decref(self.refcount.as_ref(), Some(self.location)) }
+    }
+}
+
+impl fmt::Debug for RefGuard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RefGuard {{ {:p} acquired at {} }}", self.refcount, self.location)
+    }
+}
+
+// the count is only ever touched atomically, so guards can be shared and sent between threads
+
+unsafe impl Send for RefGuard {}
+unsafe impl Sync for RefGuard {}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    static FREED: AtomicUsize = AtomicUsize::new(0);
+
+    extern "C" fn count_free(_obj: *const ReferenceCount) {
+        FREED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn guards_release_references() {
+        let mut refcount = ReferenceCount { count: 1, free: count_free };
+        let ptr = &mut refcount as *mut ReferenceCount;
+        let count = || unsafe { (*ptr).count };
+        let original = unsafe { RefGuard::adopt(ptr).unwrap() };
+        let copy = original.clone();
+        assert_eq!(count(), 2);
+        drop(original);
+        assert_eq!(count(), 1);
+        assert_eq!(FREED.load(Ordering::SeqCst), 0);
+        drop(copy);
+        assert_eq!(count(), 0);
+        assert_eq!(FREED.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn null_refcounts_are_rejected() {
+        assert!(unsafe { RefGuard::new(std::ptr::null()) }.is_none());
+    }
+}
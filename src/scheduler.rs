@@ -0,0 +1,275 @@
+/// A small scheduler for running one-shot and periodic tasks on a background thread, for plugin work
+/// like sending periodic REMB or RTCP packets, pushing stats or cleaning up rooms.
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// What delays too long to represent as an `Instant`, like `Duration::MAX`, are clamped to.
+const MAX_DELAY: Duration = Duration::from_secs(10 * 365 * 24 * 60 * 60);
+
+fn deadline_after(start: Instant, delay: Duration) -> Instant {
+    start.checked_add(delay).unwrap_or_else(|| start + MAX_DELAY)
+}
+
+struct Task {
+    job: Box<dyn FnMut() + Send>,
+    interval: Option<Duration>,
+    cancelled: Arc<AtomicBool>,
+}
+
+#[derive(Default)]
+struct State {
+    queue: BinaryHeap<Reverse<(Instant, u64)>>,
+    tasks: HashMap<u64, Task>,
+    next_id: u64,
+    shutdown: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    wakeup: Condvar,
+}
+
+impl Shared {
+    fn run(&self) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.shutdown {
+                return;
+            }
+            let (deadline, id) = match state.queue.peek() {
+                None => {
+                    state = self.wakeup.wait(state).unwrap();
+                    continue;
+                }
+                Some(Reverse(next)) => *next,
+            };
+            let now = Instant::now();
+            if deadline > now {
+                state = self.wakeup.wait_timeout(state, deadline - now).unwrap().0;
+                continue;
+            }
+            state.queue.pop();
+            let mut task = match state.tasks.remove(&id) {
+                Some(task) => task,
+                None => continue, // cancelled while it was waiting
+            };
+            drop(state);
+            if panic::catch_unwind(AssertUnwindSafe(|| (task.job)())).is_err() {
+                crate::janus_err!("Scheduled task {} panicked; it won't be run again.", id);
+                task.cancelled.store(true, Ordering::SeqCst);
+            }
+            state = self.state.lock().unwrap();
+            match task.interval {
+                Some(interval) if !task.cancelled.load(Ordering::SeqCst) && !state.shutdown => {
+                    // stay on the original cadence unless we've fallen behind it entirely
+                    let next = deadline_after(deadline, interval).max(Instant::now());
+                    state.queue.push(Reverse((next, id)));
+                    state.tasks.insert(id, task);
+                }
+                _ => {
+                    // the job may own things with their own cleanup, which shouldn't run with the lock held
+                    drop(state);
+                    drop(task);
+                    state = self.state.lock().unwrap();
+                }
+            }
+        }
+    }
+}
+
+/// A handle to a scheduled task, which can be used to cancel it. Dropping the handle doesn't cancel the task.
+#[derive(Debug, Clone)]
+pub struct TaskHandle {
+    id: u64,
+    cancelled: Arc<AtomicBool>,
+    shared: Weak<Shared>,
+}
+
+impl TaskHandle {
+    /// Prevents the task from running again. A task which is currently running will finish its current run.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        if let Some(shared) = self.shared.upgrade() {
+            let task = shared.state.lock().unwrap().tasks.remove(&self.id);
+            drop(task); // after releasing the lock
+        }
+    }
+
+    /// Returns whether the task has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Runs scheduled tasks on a single background thread. Tasks should be short; a long-running task delays
+/// every other task. Plugins should shut the scheduler down in `destroy`, which happens automatically when
+/// it's dropped.
+pub struct Scheduler {
+    shared: Arc<Shared>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Scheduler {
+    /// Creates a scheduler and starts its thread.
+    pub fn new() -> io::Result<Self> {
+        let shared = Arc::new(Shared::default());
+        let worker = Arc::clone(&shared);
+        let thread = thread::Builder::new().name("scheduler".into()).spawn(move || worker.run())?;
+        Ok(Self { shared, thread: Mutex::new(Some(thread)) })
+    }
+
+    fn schedule(&self, delay: Duration, interval: Option<Duration>, job: Box<dyn FnMut() + Send>) -> TaskHandle {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let mut state = self.shared.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        if state.shutdown {
+            cancelled.store(true, Ordering::SeqCst);
+        } else {
+            state.queue.push(Reverse((deadline_after(Instant::now(), delay), id)));
+            state.tasks.insert(id, Task { job, interval, cancelled: Arc::clone(&cancelled) });
+            self.shared.wakeup.notify_one();
+        }
+        TaskHandle { id, cancelled, shared: Arc::downgrade(&self.shared) }
+    }
+
+    /// Runs the given function once, after the given delay.
+    pub fn schedule_once<F>(&self, delay: Duration, f: F) -> TaskHandle
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let mut f = Some(f);
+        self.schedule(delay, None, Box::new(move || if let Some(f) = f.take() { f() }))
+    }
+
+    /// Runs the given function repeatedly, every `interval`, starting one interval from now.
+    pub fn schedule_repeating<F>(&self, interval: Duration, f: F) -> TaskHandle
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.schedule(interval, Some(interval), Box::new(f))
+    }
+
+    /// Cancels every task and waits for the scheduler thread to exit. Tasks scheduled afterwards never run.
+    pub fn shutdown(&self) {
+        let tasks = {
+            let mut state = self.shared.state.lock().unwrap();
+            state.shutdown = true;
+            for task in state.tasks.values() {
+                task.cancelled.store(true, Ordering::SeqCst);
+            }
+            state.queue.clear();
+            self.shared.wakeup.notify_all();
+            std::mem::take(&mut state.tasks)
+        };
+        drop(tasks); // after releasing the lock
+        if let Some(thread) = self.thread.lock().unwrap().take() {
+            if thread.thread().id() != thread::current().id() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+impl fmt::Debug for Scheduler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.shared.state.lock().unwrap();
+        write!(f, "Scheduler {{ tasks: {}, shutdown: {} }}", state.tasks.len(), state.shutdown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::sync::mpsc;
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn runs_one_shot_tasks_once() {
+        let scheduler = Scheduler::new().unwrap();
+        let (tx, rx) = mpsc::channel();
+        scheduler.schedule_once(Duration::from_millis(10), move || tx.send(()).unwrap());
+        assert!(rx.recv_timeout(WAIT).is_ok());
+        assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+    }
+
+    #[test]
+    fn repeats_until_cancelled() {
+        let scheduler = Scheduler::new().unwrap();
+        let (tx, rx) = mpsc::channel();
+        let task = scheduler.schedule_repeating(Duration::from_millis(5), move || { let _ = tx.send(()); });
+        for _ in 0..3 {
+            assert!(rx.recv_timeout(WAIT).is_ok());
+        }
+        task.cancel();
+        assert!(task.is_cancelled());
+        while rx.recv_timeout(Duration::from_millis(50)).is_ok() {} // drain anything in flight
+        assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+    }
+
+    #[test]
+    fn shutdown_cancels_pending_tasks() {
+        let scheduler = Scheduler::new().unwrap();
+        let (tx, rx) = mpsc::channel();
+        let task = scheduler.schedule_once(Duration::from_millis(20), move || tx.send(()).unwrap());
+        scheduler.shutdown();
+        assert!(task.is_cancelled());
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn clamp_huge_delays() {
+        let scheduler = Scheduler::new().unwrap();
+        let once = scheduler.schedule_once(Duration::MAX, || {});
+        let (tx, rx) = mpsc::channel();
+        let repeating = scheduler.schedule_repeating(Duration::from_millis(5), move || { let _ = tx.send(()); });
+        assert!(rx.recv_timeout(WAIT).is_ok());
+        once.cancel();
+        repeating.cancel();
+        let forever = scheduler.schedule_repeating(Duration::MAX, || {});
+        assert!(!forever.is_cancelled());
+    }
+
+    #[test]
+    fn drop_tasks_without_the_lock() {
+        // a job which schedules another task when it's dropped would deadlock if it were dropped with the lock held
+        struct Reschedule(Arc<Scheduler>, mpsc::Sender<()>);
+        impl Drop for Reschedule {
+            fn drop(&mut self) {
+                self.0.schedule_once(Duration::MAX, || {});
+                let _ = self.1.send(());
+            }
+        }
+        let scheduler = Arc::new(Scheduler::new().unwrap());
+        let (tx, rx) = mpsc::channel();
+        for _ in 0..3 {
+            let reschedule = Reschedule(Arc::clone(&scheduler), tx.clone());
+            let task = scheduler.schedule_once(Duration::from_secs(60), move || { let _ = &reschedule; });
+            task.cancel();
+        }
+        assert_eq!(rx.try_iter().count(), 3);
+        let reschedule = Reschedule(Arc::clone(&scheduler), tx.clone());
+        scheduler.schedule_once(Duration::from_millis(1), move || { let _ = &reschedule; });
+        assert!(rx.recv_timeout(WAIT).is_ok());
+        let reschedule = Reschedule(Arc::clone(&scheduler), tx);
+        scheduler.schedule_once(Duration::from_secs(60), move || { let _ = &reschedule; });
+        scheduler.shutdown();
+        assert!(rx.recv_timeout(WAIT).is_ok());
+    }
+}
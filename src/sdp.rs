@@ -1,7 +1,11 @@
 /// Utilities to write SDP offers and answers using Janus's SDP parsing machinery.
+///
+/// By default, SDPs are parsed and written by libjanus. With the `rust-sdp` feature, they're parsed and written by an
+/// equivalent pure-Rust implementation instead, so that code which manipulates SDPs can run outside of Janus. Since
+/// libjanus is only available inside Janus, this crate's SDP tests only run with the feature enabled. Generating offers
+/// and answers always requires libjanus.
 
 use glib_sys;
-use libc;
 use janus_plugin_sys as ffi;
 use serde::de::{self, Deserialize, Deserializer, Unexpected, Visitor};
 use serde::ser::{Serialize, Serializer};
@@ -15,6 +19,31 @@ use std::ops::Deref;
 use std::str;
 use crate::utils::GLibString;
 
+mod builder;
+mod diff;
+mod extmap;
+mod fmtp;
+mod mline;
+mod negotiate;
+mod payload;
+mod simulcast;
+#[cfg(not(feature = "rust-sdp"))]
+mod janus;
+#[cfg(not(feature = "rust-sdp"))]
+use self::janus as backend;
+#[cfg(feature = "rust-sdp")]
+mod native;
+#[cfg(feature = "rust-sdp")]
+use self::native as backend;
+pub use self::builder::{AnswerBuilder, OfferBuilder, SdpGenerateError};
+pub use self::diff::{IceCredentials, MLineDiff, MediaChange, MediaCodec, SdpDiff, SessionChange};
+pub use self::extmap::Extmap;
+pub use self::fmtp::{CodecParameters, FmtpParameters, H264Parameters, OpusParameters, RtxParameters, Vp9Parameters};
+pub use self::mline::{Attribute, MLine};
+pub use self::negotiate::{Codec, CodecPolicy, CodecPreference, NegotiatedCodec, Negotiation};
+pub use self::simulcast::{Rid, Simulcast, SimulcastAlternative, SsrcGroup, StreamDirection};
+use self::mline::GListData;
+
 pub type RawSdp = ffi::sdp::janus_sdp;
 pub type RawMLine = ffi::sdp::janus_sdp_mline;
 pub type RawAttribute = ffi::sdp::janus_sdp_attribute;
@@ -150,27 +179,21 @@ impl fmt::Display for SdpParseError {
 }
 
 impl Sdp {
+    /// Takes ownership of the given SDP, which must have been produced by the SDP backend in use, i.e. libjanus
+    /// unless the `rust-sdp` feature is enabled.
     pub unsafe fn new(ptr: *mut RawSdp) -> Option<Self> {
         ptr.as_mut().map(|p| Self { ptr: p })
     }
 
     /// Parses an SDP offer string from a client into a structured SDP object.
     pub fn parse(offer: &CStr) -> Result<Self, SdpParseError> {
-        let mut error_buffer = Vec::with_capacity(512);
-        let error_ptr = error_buffer.as_mut_ptr() as *mut _;
-        unsafe {
-            let result = ffi::sdp::janus_sdp_parse(offer.as_ptr(), error_ptr, error_buffer.capacity());
-            Sdp::new(result).ok_or_else(|| {
-                error_buffer.set_len(libc::strlen(error_ptr));
-                SdpParseError { buffer: error_buffer }
-            })
-        }
+        unsafe { backend::parse(offer).map(|ptr| Self { ptr }) }
     }
 
     /// Gets the payload type number for a codec in this SDP, or None if the codec isn't present.
     pub fn get_payload_type(&self, codec_name: &CStr) -> Option<i32> {
         unsafe {
-            match ffi::sdp::janus_sdp_get_codec_pt(self.ptr, codec_name.as_ptr()) {
+            match backend::get_codec_pt(self.ptr, codec_name, None) {
                 err if err < 0 => None,
                 n => Some(n),
             }
@@ -180,7 +203,7 @@ impl Sdp {
     /// Gets the payload type number for a codec and provided video profile in this SDP, or None if the codec isn't present with the provided video profile.
     pub fn get_payload_type_full(&self, codec_name: &CStr, profile: &CStr) -> Option<i32> {
         unsafe {
-            match ffi::sdp::janus_sdp_get_codec_pt_full(self.ptr, codec_name.as_ptr(), profile.as_ptr()) {
+            match backend::get_codec_pt(self.ptr, codec_name, Some(profile)) {
                 err if err < 0 => None,
                 n => Some(n),
             }
@@ -189,79 +212,71 @@ impl Sdp {
 
     /// Adds an attribute for the m-line with the given payload type.
     pub fn add_attribute(&mut self, pt: i32, name: &CStr, contents: &CStr) {
-        for (_media, m_lines) in self.get_mlines() {
-            unsafe {
-                for m_line in m_lines {
-                    if !glib_sys::g_list_find(m_line.ptypes, pt as *const _).is_null() {
-                        let attr = ffi::sdp::janus_sdp_attribute_create(name.as_ptr(), contents.as_ptr());
-                        ffi::sdp::janus_sdp_attribute_add_to_mline(m_line as *mut _, attr as *mut _);
-                    }
-                }
+        let name = name.to_str().expect("Invalid attribute name :(");
+        let contents = contents.to_str().expect("Invalid attribute value :(");
+        for m_line in self.mlines_mut() {
+            if m_line.payload_types().any(|p| p == pt) {
+                m_line.add_attribute(name, Some(contents));
             }
         }
     }
 
     /// Rewrites any references from one dynamically assigned payload type in this SDP to another dynamically assigned
-    /// payload type.
+    /// payload type. See `rewrite_payload_types` to rewrite several at once.
     pub fn rewrite_payload_type(&mut self, from: i32, to: i32) {
-        let from_pt_string = from.to_string();
-        let to_pt_string = to.to_string();
-        for (_media, m_lines) in self.get_mlines() {
-            unsafe {
-                for m_line in m_lines {
-                    // 1. replace the payload type ID in this media line's payload type list
-                    if !glib_sys::g_list_find(m_line.ptypes, from as *const _).is_null() {
-                        // payload type data in the list is cast to pointers
-                        m_line.ptypes = glib_sys::g_list_remove(m_line.ptypes, from as *const _);
-                        m_line.ptypes = glib_sys::g_list_prepend(m_line.ptypes, to as *mut _);
-                    }
-                    // 2. rewrite the values of attribute lines with the old payload type to have the new payload type
-                    let mut attr_node = m_line.attributes;
-                    while let Some(node) = attr_node.as_ref() {
-                        let next = node.next; // we might delete this link, so grab next now!
-                        let data = node.data as *mut RawAttribute;
-                        let attr = data.as_ref().expect("Null data in SDP attribute node :(");
-                        let name = CStr::from_ptr(attr.name).to_str().expect("Invalid attribute name in SDP :(");
-                        if MEDIA_PAYLOAD_ATTRIBUTES.contains(&name) {
-                            // each of the attributes with payload types in the values look like "$pt $stuff"
-                            // where $stuff is specifying payload-type-specfic options; just rewrite $pt
-                            let value = CStr::from_ptr(attr.value).to_str().expect("Invalid attribute value in SDP :(");
-                            if value.starts_with(&from_pt_string) {
-                                // value string is copied into the attribute
-                                let new_val = CString::new(value.replacen(&from_pt_string, &to_pt_string, 1)).unwrap();
-                                let new_attr = ffi::sdp::janus_sdp_attribute_create(attr.name, new_val.as_ptr());
-                                m_line.attributes = glib_sys::g_list_prepend(m_line.attributes, new_attr as *mut _);
-                                m_line.attributes = glib_sys::g_list_delete_link(m_line.attributes, attr_node);
-                                ffi::sdp::janus_sdp_attribute_destroy(data);
-                            }
-                        }
-                        attr_node = next;
-                    }
-                }
-            }
-        }
+        let mut mapping = HashMap::new();
+        mapping.insert(from, to);
+        self.rewrite_payload_types(&mapping);
     }
 
     /// Returns a map of all the SDP media lines per SDP media type.
-    pub fn get_mlines(&self) -> HashMap<MediaType, Vec<&mut RawMLine>> {
+    #[deprecated(note = "Use `mlines_mut`, which keeps the m-lines in order and doesn't expose raw pointers.")]
+    pub fn get_mlines(&mut self) -> HashMap<MediaType, Vec<&mut RawMLine>> {
         let mut result = HashMap::new();
+        for m_line in self.mlines_mut() {
+            result.entry(m_line.media_type()).or_insert_with(Vec::new).push(m_line.as_raw_mut());
+        }
+        result
+    }
+
+    /// Returns the media lines in this SDP, in order.
+    pub fn mlines(&self) -> impl Iterator<Item = &MLine> + '_ {
+        unsafe { GListData::new((*self.ptr).m_lines).map(|ml| MLine::from_raw(&*(ml as *const RawMLine))) }
+    }
+
+    /// Returns the media lines in this SDP, in order, for modification.
+    pub fn mlines_mut(&mut self) -> impl Iterator<Item = &mut MLine> + '_ {
+        unsafe { GListData::new((*self.ptr).m_lines).map(|ml| MLine::from_raw_mut(&mut *(ml as *mut RawMLine))) }
+    }
+
+    /// Returns the session-level attributes in this SDP, in order.
+    pub fn attributes(&self) -> impl Iterator<Item = &Attribute> + '_ {
+        unsafe { GListData::new((*self.ptr).attributes).map(|a| Attribute::from_raw(&*(a as *const RawAttribute))) }
+    }
+
+    /// Returns the session-level attributes in this SDP, in order, for modification.
+    pub fn attributes_mut(&mut self) -> impl Iterator<Item = &mut Attribute> + '_ {
+        unsafe { GListData::new((*self.ptr).attributes).map(|a| Attribute::from_raw_mut(&mut *(a as *mut RawAttribute))) }
+    }
+
+    /// Appends a session-level attribute to this SDP.
+    ///
+    /// Panics if the name or value contains a nul byte.
+    pub fn add_session_attribute(&mut self, name: &str, value: Option<&str>) {
         unsafe {
-            let mut ml_node = (*self.ptr).m_lines;
-            while let Some(node) = ml_node.as_ref() {
-                let ml = (node.data as *mut RawMLine).as_mut().expect("Null data in SDP media node :(");
-                result.entry(ml.type_).or_insert_with(Vec::new).push(ml);
-                ml_node = node.next;
-            }
-            result
+            let attr = mline::create_attribute(name, value);
+            (*self.ptr).attributes = glib_sys::g_list_append((*self.ptr).attributes, attr as *mut _);
         }
     }
 
+    /// Removes every session-level attribute which the predicate rejects.
+    pub fn retain_session_attributes<F: FnMut(&Attribute) -> bool>(&mut self, keep: F) {
+        unsafe { mline::retain_attributes(&mut (*self.ptr).attributes, keep) }
+    }
+
     /// Writes this SDP into an owned C-style string.
     pub fn to_glibstring(&self) -> GLibString {
-        unsafe {
-            let sdp = ffi::sdp::janus_sdp_write(self.ptr);
-            GLibString::from_chars(sdp).expect("Mysterious error writing SDP to string :(")
-        }
+        unsafe { backend::write(self.ptr) }
     }
 }
 
@@ -276,7 +291,7 @@ impl Deref for Sdp {
 impl Drop for Sdp {
     fn drop(&mut self) {
         unsafe {
-            ffi::sdp::janus_sdp_destroy(self.ptr);
+            backend::destroy(self.ptr);
         }
     }
 }
@@ -318,25 +333,40 @@ impl<'de> Deserialize<'de> for Sdp {
 
 unsafe impl Send for Sdp {}
 
+/// Calls a libjanus function which takes an SDP, e.g. `generate_answer`. Used by the `answer_sdp` macro.
+#[doc(hidden)]
+pub unsafe fn with_janus_sdp<R>(sdp: &Sdp, f: impl FnOnce(*mut RawSdp) -> R) -> R {
+    backend::with_janus_sdp(sdp, f)
+}
+
+/// Takes ownership of an SDP returned by a libjanus function, e.g. `generate_answer`. Used by the `answer_sdp` and
+/// `offer_sdp` macros.
+#[doc(hidden)]
+pub unsafe fn adopt_janus_sdp(ptr: *mut RawSdp) -> Option<Sdp> {
+    backend::adopt_janus_sdp(ptr)
+}
+
 #[macro_export]
 /// Given an SDP offer from a client, generates an SDP answer.
-/// (This has to be a macro because `generate_answer` is variadic.)
+/// (This has to be a macro because `generate_answer` is variadic.) Prefer `AnswerBuilder`, which checks the
+/// type of each parameter's value.
 macro_rules! answer_sdp {
     ($sdp:expr $(, $param:expr, $value:expr)* $(,)*) => {
         unsafe {
-            let result = $crate::sdp::generate_answer(
-                $sdp.ptr,
+            let result = $crate::sdp::with_janus_sdp(&$sdp, |offer| $crate::sdp::generate_answer(
+                offer,
                 $($param, $value,)*
                 $crate::sdp::OfferAnswerParameters::Done
-            );
-            $crate::sdp::Sdp::new(result).expect("Mysterious error generating SDP answer :(")
+            ));
+            $crate::sdp::adopt_janus_sdp(result).expect("Mysterious error generating SDP answer :(")
         }
     }
 }
 
 #[macro_export]
 /// Generates an SDP offer given some parameters.
-/// (This has to be a macro because `generate_offer` is variadic.)
+/// (This has to be a macro because `generate_offer` is variadic.) Prefer `OfferBuilder`, which checks the
+/// type of each parameter's value.
 macro_rules! offer_sdp {
     ($name:expr, $address:expr $(, $param:expr, $value:expr)* $(,)*) => {
         unsafe {
@@ -346,7 +376,7 @@ macro_rules! offer_sdp {
                 $($param, $value,)*
                 $crate::sdp::OfferAnswerParameters::Done
             );
-            $crate::sdp::Sdp::new(result).expect("Mysterious error generating SDP offer :(")
+            $crate::sdp::adopt_janus_sdp(result).expect("Mysterious error generating SDP offer :(")
         }
     }
 }
@@ -0,0 +1,494 @@
+/// Typed builders for generating SDP offers and answers with `janus_sdp_generate_offer` and
+/// `janus_sdp_generate_answer`, which take their parameters as a variadic list of property-value pairs.
+use janus_plugin_sys as ffi;
+use std::error::Error;
+use std::ffi::CString;
+use std::fmt;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use super::{backend, AudioCodec, MediaDirection, OfferAnswerParameters, RawSdp, Sdp, VideoCodec};
+
+/// The number of property-value pairs with an int value, a string value, and a string and an int value (i.e. offered
+/// header extensions) passed to Janus on every call.
+const INT_PARAMS: usize = 16;
+const STR_PARAMS: usize = 16;
+const EXT_PARAMS: usize = 16;
+
+/// An error indicating that we failed to generate an SDP offer or answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SdpGenerateError {
+    /// A string parameter contained a nul byte, so it couldn't be passed to Janus.
+    InvalidString(String),
+    /// More parameters were given than fit in a single call to Janus.
+    TooManyParameters,
+    /// Janus failed to generate the SDP; the reason is in the Janus log.
+    Failed,
+}
+
+impl Error for SdpGenerateError {}
+
+impl fmt::Display for SdpGenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SdpGenerateError::InvalidString(s) => write!(f, "SDP parameter contains a nul byte: {:?}", s),
+            SdpGenerateError::TooManyParameters => f.write_str("Too many SDP parameters."),
+            SdpGenerateError::Failed => f.write_str("Janus failed to generate the SDP; see the Janus log for details."),
+        }
+    }
+}
+
+/// The property-value lists in the form Janus expects, grouped by the types of their values, along with the first
+/// error hit while building them.
+#[derive(Debug, Default)]
+struct Params {
+    ints: Vec<(c_int, c_int)>,
+    strs: Vec<(c_int, CString)>,
+    exts: Vec<(c_int, CString, c_int)>,
+    error: Option<SdpGenerateError>,
+}
+
+/// The parameters for one call to Janus, each group padded out to its fixed size.
+#[derive(Debug)]
+struct Layout {
+    ints: Vec<(c_int, c_int)>,
+    strs: Vec<(c_int, *const c_char)>,
+    exts: Vec<(c_int, *const c_char, c_int)>,
+}
+
+/// Pads the entries out to the given number, by putting copies of the filler in front of them, so that every given
+/// entry is still read after (and so overrides) the filler.
+fn pad<T: Clone>(entries: Vec<T>, len: usize, filler: T) -> Result<Vec<T>, SdpGenerateError> {
+    if entries.len() > len {
+        return Err(SdpGenerateError::TooManyParameters);
+    }
+    let mut result = vec![filler; len - entries.len()];
+    result.extend(entries);
+    Ok(result)
+}
+
+impl Params {
+    fn int(&mut self, param: OfferAnswerParameters, value: c_int) {
+        self.ints.push((param as c_int, value));
+    }
+
+    fn flag(&mut self, param: OfferAnswerParameters, value: bool) {
+        self.int(param, value as c_int);
+    }
+
+    fn cstring(&mut self, value: &str) -> Option<CString> {
+        let result = CString::new(value).ok();
+        if result.is_none() {
+            self.error.get_or_insert_with(|| SdpGenerateError::InvalidString(value.to_owned()));
+        }
+        result
+    }
+
+    fn str(&mut self, param: OfferAnswerParameters, value: &str) {
+        if let Some(s) = self.cstring(value) {
+            self.strs.push((param as c_int, s));
+        }
+    }
+
+    fn extension(&mut self, param: OfferAnswerParameters, uri: &str, id: c_int) {
+        if let Some(s) = self.cstring(uri) {
+            self.exts.push((param as c_int, s, id));
+        }
+    }
+
+    /// Lays the parameters out for a variadic call, in which every value has the type Janus reads it as. Janus reads
+    /// the parameters in order until `Done`, so to pass any parameters in a fixed argument list, each group is padded
+    /// with a parameter which Janus treats the same as leaving it out: telephone events off, no custom audio fmtp, and
+    /// a repeat of the first header extension. If there aren't any header extensions, there's nothing to repeat, so that
+    /// group is left empty and must be left out of the call.
+    fn layout(&self) -> Result<Layout, SdpGenerateError> {
+        if let Some(ref e) = self.error {
+            return Err(e.clone());
+        }
+        let strs = self.strs.iter().map(|(p, s)| (*p, s.as_ptr())).collect();
+        let exts = self.exts.iter().map(|(p, s, id)| (*p, s.as_ptr(), *id)).collect::<Vec<_>>();
+        let ext_filler = exts.first().cloned();
+        Ok(Layout {
+            ints: pad(self.ints.clone(), INT_PARAMS, (OfferAnswerParameters::AudioDtmf as c_int, 0))?,
+            strs: pad(strs, STR_PARAMS, (OfferAnswerParameters::AudioFmtp as c_int, ptr::null()))?,
+            exts: match ext_filler {
+                Some(filler) => pad(exts, EXT_PARAMS, filler)?,
+                None => exts,
+            },
+        })
+    }
+}
+
+// passes every parameter in the layout to the given variadic function, after the fixed arguments, followed by `Done`;
+// the header extensions are only passed if given
+macro_rules! call_with_layout {
+    ($f:path, ($($fixed:expr),*), $layout:expr $(, $exts:expr)?) => {{
+        let Layout { ints: ref i, strs: ref s, .. } = $layout;
+        $f($($fixed,)*
+           i[0].0, i[0].1, i[1].0, i[1].1, i[2].0, i[2].1, i[3].0, i[3].1, i[4].0, i[4].1, i[5].0, i[5].1, i[6].0,
+           i[6].1, i[7].0, i[7].1, i[8].0, i[8].1, i[9].0, i[9].1, i[10].0, i[10].1, i[11].0, i[11].1, i[12].0,
+           i[12].1, i[13].0, i[13].1, i[14].0, i[14].1, i[15].0, i[15].1,
+           s[0].0, s[0].1, s[1].0, s[1].1, s[2].0, s[2].1, s[3].0, s[3].1, s[4].0, s[4].1, s[5].0, s[5].1, s[6].0,
+           s[6].1, s[7].0, s[7].1, s[8].0, s[8].1, s[9].0, s[9].1, s[10].0, s[10].1, s[11].0, s[11].1, s[12].0,
+           s[12].1, s[13].0, s[13].1, s[14].0, s[14].1, s[15].0, s[15].1,
+$(
+           $exts[0].0, $exts[0].1, $exts[0].2, $exts[1].0, $exts[1].1, $exts[1].2, $exts[2].0, $exts[2].1, $exts[2].2,
+           $exts[3].0, $exts[3].1, $exts[3].2, $exts[4].0, $exts[4].1, $exts[4].2, $exts[5].0, $exts[5].1, $exts[5].2,
+           $exts[6].0, $exts[6].1, $exts[6].2, $exts[7].0, $exts[7].1, $exts[7].2, $exts[8].0, $exts[8].1, $exts[8].2,
+           $exts[9].0, $exts[9].1, $exts[9].2, $exts[10].0, $exts[10].1, $exts[10].2, $exts[11].0, $exts[11].1,
+           $exts[11].2, $exts[12].0, $exts[12].1, $exts[12].2, $exts[13].0, $exts[13].1, $exts[13].2, $exts[14].0,
+           $exts[14].1, $exts[14].2, $exts[15].0, $exts[15].1, $exts[15].2,
+)?
+           OfferAnswerParameters::Done as c_int)
+    }}
+}
+
+fn generated(result: *mut RawSdp) -> Result<Sdp, SdpGenerateError> {
+    unsafe { backend::adopt_janus_sdp(result).ok_or(SdpGenerateError::Failed) }
+}
+
+/// Generates an SDP answer to an offer. Anything not set is left up to Janus's defaults.
+///
+/// ```ignore
+/// let answer = AnswerBuilder::new(&offer)
+///     .audio_codec(AudioCodec::Opus)
+///     .video_codec(VideoCodec::H264)
+///     .h264_profile("42e01f")
+///     .video_direction(MediaDirection::JANUS_SDP_RECVONLY)
+///     .build()?;
+/// ```
+#[derive(Debug)]
+pub struct AnswerBuilder<'a> {
+    offer: &'a Sdp,
+    params: Params,
+}
+
+impl<'a> AnswerBuilder<'a> {
+    /// Starts an answer to the given offer.
+    pub fn new(offer: &'a Sdp) -> Self {
+        Self { offer, params: Params::default() }
+    }
+
+    /// Whether to accept or reject audio.
+    pub fn audio(mut self, accept: bool) -> Self {
+        self.params.flag(OfferAnswerParameters::Audio, accept);
+        self
+    }
+
+    /// Whether to accept or reject video.
+    pub fn video(mut self, accept: bool) -> Self {
+        self.params.flag(OfferAnswerParameters::Video, accept);
+        self
+    }
+
+    /// Whether to accept or reject data channels.
+    pub fn data(mut self, accept: bool) -> Self {
+        self.params.flag(OfferAnswerParameters::Data, accept);
+        self
+    }
+
+    /// The direction of the audio stream.
+    pub fn audio_direction(mut self, direction: MediaDirection) -> Self {
+        self.params.int(OfferAnswerParameters::AudioDirection, direction as c_int);
+        self
+    }
+
+    /// The direction of the video stream.
+    pub fn video_direction(mut self, direction: MediaDirection) -> Self {
+        self.params.int(OfferAnswerParameters::VideoDirection, direction as c_int);
+        self
+    }
+
+    /// The audio codec to answer with.
+    pub fn audio_codec(mut self, codec: AudioCodec) -> Self {
+        self.params.str(OfferAnswerParameters::AudioCodec, codec.to_str());
+        self
+    }
+
+    /// The video codec to answer with.
+    pub fn video_codec(mut self, codec: VideoCodec) -> Self {
+        self.params.str(OfferAnswerParameters::VideoCodec, codec.to_str());
+        self
+    }
+
+    /// The VP9 profile to answer with, e.g. "2".
+    pub fn vp9_profile(mut self, profile: &str) -> Self {
+        self.params.str(OfferAnswerParameters::Vp9Profile, profile);
+        self
+    }
+
+    /// The H.264 profile-level-id to answer with, e.g. "42e01f".
+    pub fn h264_profile(mut self, profile: &str) -> Self {
+        self.params.str(OfferAnswerParameters::H264Profile, profile);
+        self
+    }
+
+    /// Whether to negotiate telephone events.
+    pub fn audio_dtmf(mut self, dtmf: bool) -> Self {
+        self.params.flag(OfferAnswerParameters::AudioDtmf, dtmf);
+        self
+    }
+
+    /// A custom fmtp string for the audio codec.
+    pub fn audio_fmtp(mut self, fmtp: &str) -> Self {
+        self.params.str(OfferAnswerParameters::AudioFmtp, fmtp);
+        self
+    }
+
+    /// A custom fmtp string for the video codec. Ignored if a VP9 or H.264 profile is set for a matching codec.
+    pub fn video_fmtp(mut self, fmtp: &str) -> Self {
+        self.params.str(OfferAnswerParameters::VideoFmtp, fmtp);
+        self
+    }
+
+    /// Whether to add the default RTCP feedback attributes for video.
+    pub fn video_rtcpfb_defaults(mut self, enabled: bool) -> Self {
+        self.params.flag(OfferAnswerParameters::VideoRtcpfbDefaults, enabled);
+        self
+    }
+
+    /// Whether to use the legacy SCTP data channel format.
+    pub fn data_legacy(mut self, legacy: bool) -> Self {
+        self.params.flag(OfferAnswerParameters::DataLegacy, legacy);
+        self
+    }
+
+    /// Accepts the RTP header extension with the given URI, if the offer has it. May be called repeatedly.
+    pub fn extension(mut self, uri: &str) -> Self {
+        self.params.str(OfferAnswerParameters::AcceptExtmap, uri);
+        self
+    }
+
+    /// Generates the answer.
+    pub fn build(self) -> Result<Sdp, SdpGenerateError> {
+        let layout = self.params.layout()?;
+        generated(unsafe {
+            backend::with_janus_sdp(self.offer, |offer| call_with_layout!(ffi::sdp::janus_sdp_generate_answer, (offer), layout))
+        })
+    }
+}
+
+/// Generates an SDP offer. Anything not set is left up to Janus's defaults.
+///
+/// ```ignore
+/// let offer = OfferBuilder::new("Janus", "127.0.0.1")
+///     .audio_codec(AudioCodec::Opus)
+///     .audio_payload_type(111)
+///     .audio_extension("urn:ietf:params:rtp-hdrext:ssrc-audio-level", 1)
+///     .data(false)
+///     .build()?;
+/// ```
+#[derive(Debug)]
+pub struct OfferBuilder {
+    name: Result<CString, SdpGenerateError>,
+    address: Result<CString, SdpGenerateError>,
+    params: Params,
+}
+
+impl OfferBuilder {
+    /// Starts an offer with the given session name and connection address.
+    pub fn new(name: &str, address: &str) -> Self {
+        let cstring = |s: &str| CString::new(s).map_err(|_| SdpGenerateError::InvalidString(s.to_owned()));
+        Self { name: cstring(name), address: cstring(address), params: Params::default() }
+    }
+
+    /// Whether to offer audio.
+    pub fn audio(mut self, offer: bool) -> Self {
+        self.params.flag(OfferAnswerParameters::Audio, offer);
+        self
+    }
+
+    /// Whether to offer video.
+    pub fn video(mut self, offer: bool) -> Self {
+        self.params.flag(OfferAnswerParameters::Video, offer);
+        self
+    }
+
+    /// Whether to offer data channels.
+    pub fn data(mut self, offer: bool) -> Self {
+        self.params.flag(OfferAnswerParameters::Data, offer);
+        self
+    }
+
+    /// The direction of the audio stream.
+    pub fn audio_direction(mut self, direction: MediaDirection) -> Self {
+        self.params.int(OfferAnswerParameters::AudioDirection, direction as c_int);
+        self
+    }
+
+    /// The direction of the video stream.
+    pub fn video_direction(mut self, direction: MediaDirection) -> Self {
+        self.params.int(OfferAnswerParameters::VideoDirection, direction as c_int);
+        self
+    }
+
+    /// The audio codec to offer.
+    pub fn audio_codec(mut self, codec: AudioCodec) -> Self {
+        self.params.str(OfferAnswerParameters::AudioCodec, codec.to_str());
+        self
+    }
+
+    /// The video codec to offer.
+    pub fn video_codec(mut self, codec: VideoCodec) -> Self {
+        self.params.str(OfferAnswerParameters::VideoCodec, codec.to_str());
+        self
+    }
+
+    /// The VP9 profile to offer, e.g. "2".
+    pub fn vp9_profile(mut self, profile: &str) -> Self {
+        self.params.str(OfferAnswerParameters::Vp9Profile, profile);
+        self
+    }
+
+    /// The H.264 profile-level-id to offer, e.g. "42e01f".
+    pub fn h264_profile(mut self, profile: &str) -> Self {
+        self.params.str(OfferAnswerParameters::H264Profile, profile);
+        self
+    }
+
+    /// The payload type to use for the audio codec.
+    pub fn audio_payload_type(mut self, pt: u8) -> Self {
+        self.params.int(OfferAnswerParameters::AudioPayloadType, c_int::from(pt));
+        self
+    }
+
+    /// The payload type to use for the video codec.
+    pub fn video_payload_type(mut self, pt: u8) -> Self {
+        self.params.int(OfferAnswerParameters::VideoPayloadType, c_int::from(pt));
+        self
+    }
+
+    /// Whether to offer telephone events.
+    pub fn audio_dtmf(mut self, dtmf: bool) -> Self {
+        self.params.flag(OfferAnswerParameters::AudioDtmf, dtmf);
+        self
+    }
+
+    /// A custom fmtp string for the audio codec.
+    pub fn audio_fmtp(mut self, fmtp: &str) -> Self {
+        self.params.str(OfferAnswerParameters::AudioFmtp, fmtp);
+        self
+    }
+
+    /// A custom fmtp string for the video codec. Ignored if a VP9 or H.264 profile is set for a matching codec.
+    pub fn video_fmtp(mut self, fmtp: &str) -> Self {
+        self.params.str(OfferAnswerParameters::VideoFmtp, fmtp);
+        self
+    }
+
+    /// Whether to add the default RTCP feedback attributes for video.
+    pub fn video_rtcpfb_defaults(mut self, enabled: bool) -> Self {
+        self.params.flag(OfferAnswerParameters::VideoRtcpfbDefaults, enabled);
+        self
+    }
+
+    /// Whether to use the legacy SCTP data channel format.
+    pub fn data_legacy(mut self, legacy: bool) -> Self {
+        self.params.flag(OfferAnswerParameters::DataLegacy, legacy);
+        self
+    }
+
+    /// Offers the RTP header extension with the given URI and ID on the audio stream. May be called repeatedly.
+    pub fn audio_extension(mut self, uri: &str, id: u8) -> Self {
+        self.params.extension(OfferAnswerParameters::AudioExtension, uri, c_int::from(id));
+        self
+    }
+
+    /// Offers the RTP header extension with the given URI and ID on the video stream. May be called repeatedly.
+    pub fn video_extension(mut self, uri: &str, id: u8) -> Self {
+        self.params.extension(OfferAnswerParameters::VideoExtension, uri, c_int::from(id));
+        self
+    }
+
+    /// Generates the offer.
+    pub fn build(self) -> Result<Sdp, SdpGenerateError> {
+        let name = self.name?;
+        let address = self.address?;
+        let layout = self.params.layout()?;
+        let (name, address) = (name.as_ptr(), address.as_ptr());
+        generated(unsafe {
+            if layout.exts.is_empty() {
+                call_with_layout!(ffi::sdp::janus_sdp_generate_offer, (name, address), layout)
+            } else {
+                call_with_layout!(ffi::sdp::janus_sdp_generate_offer, (name, address), layout, layout.exts)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn lay_out_parameters() {
+        let mut params = Params::default();
+        params.flag(OfferAnswerParameters::Audio, true);
+        params.str(OfferAnswerParameters::H264Profile, "42e01f");
+        params.extension(OfferAnswerParameters::VideoExtension, "urn:ietf:params:rtp-hdrext:toffset", 3);
+        params.extension(OfferAnswerParameters::AudioExtension, "urn:ietf:params:rtp-hdrext:sdes:mid", 4);
+        let layout = params.layout().unwrap();
+
+        assert_eq!(layout.ints.len(), INT_PARAMS);
+        assert!(layout.ints[..INT_PARAMS - 1].iter().all(|&p| p == (OfferAnswerParameters::AudioDtmf as c_int, 0)));
+        assert_eq!(layout.ints[INT_PARAMS - 1], (OfferAnswerParameters::Audio as c_int, 1));
+
+        assert_eq!(layout.strs.len(), STR_PARAMS);
+        assert!(layout.strs[..STR_PARAMS - 1].iter().all(|&p| p == (OfferAnswerParameters::AudioFmtp as c_int, ptr::null())));
+        assert_eq!(layout.strs[STR_PARAMS - 1], (OfferAnswerParameters::H264Profile as c_int, params.strs[0].1.as_ptr()));
+
+        let toffset = (OfferAnswerParameters::VideoExtension as c_int, params.exts[0].1.as_ptr(), 3);
+        let mid = (OfferAnswerParameters::AudioExtension as c_int, params.exts[1].1.as_ptr(), 4);
+        assert_eq!(layout.exts.len(), EXT_PARAMS);
+        assert!(layout.exts[..EXT_PARAMS - 1].iter().all(|&p| p == toffset));
+        assert_eq!(layout.exts[EXT_PARAMS - 1], mid);
+
+        assert!(Params::default().layout().unwrap().exts.is_empty());
+    }
+
+    #[test]
+    fn reject_invalid_parameters() {
+        let mut params = Params::default();
+        params.extension(OfferAnswerParameters::AudioExtension, "bad\0uri", 1);
+        params.flag(OfferAnswerParameters::Video, false);
+        assert_eq!(params.ints.len(), 1);
+        assert_eq!(params.layout().unwrap_err(), SdpGenerateError::InvalidString("bad\0uri".to_owned()));
+
+        let mut params = Params::default();
+        for _ in 0..=INT_PARAMS {
+            params.flag(OfferAnswerParameters::AudioDtmf, true);
+        }
+        assert_eq!(params.layout().unwrap_err(), SdpGenerateError::TooManyParameters);
+    }
+
+    #[test]
+    fn fail_before_generating_offer() {
+        let offer = OfferBuilder::new("Janus", "127.0.0.1\0").audio_codec(AudioCodec::Opus);
+        assert_eq!(offer.build().unwrap_err(), SdpGenerateError::InvalidString("127.0.0.1\0".to_owned()));
+        let offer = OfferBuilder::new("Janus", "127.0.0.1").video_fmtp("\0");
+        assert_eq!(offer.build().unwrap_err(), SdpGenerateError::InvalidString("\0".to_owned()));
+        let offer = (0..=EXT_PARAMS as u8).fold(OfferBuilder::new("Janus", "127.0.0.1"), |o, id| o.audio_extension("urn:x", id));
+        assert_eq!(offer.build().unwrap_err(), SdpGenerateError::TooManyParameters);
+    }
+
+    // the test build has no libjanus, so generating always fails the way it would if Janus returned nothing
+    #[test]
+    fn fail_to_generate_offer() {
+        let offer = OfferBuilder::new("Janus", "127.0.0.1").audio_codec(AudioCodec::Opus).data(false);
+        assert_eq!(offer.build().unwrap_err(), SdpGenerateError::Failed);
+        let offer = OfferBuilder::new("Janus", "127.0.0.1").audio_extension("urn:ietf:params:rtp-hdrext:sdes:mid", 4);
+        assert_eq!(offer.build().unwrap_err(), SdpGenerateError::Failed);
+    }
+
+    #[cfg(feature = "rust-sdp")]
+    #[test]
+    fn fail_answer() {
+        let offer = Sdp::parse(&CString::new(include_str!("testdata/chrome.sdp")).unwrap()).unwrap();
+        let answer = AnswerBuilder::new(&offer).audio_codec(AudioCodec::Opus).h264_profile("42e01f\0").build();
+        assert_eq!(answer.unwrap_err(), SdpGenerateError::InvalidString("42e01f\0".to_owned()));
+        let answer = (0..=STR_PARAMS).fold(AnswerBuilder::new(&offer), |a, _| a.extension("urn:ietf:params:rtp-hdrext:sdes:mid"));
+        assert_eq!(answer.build().unwrap_err(), SdpGenerateError::TooManyParameters);
+        let answer = AnswerBuilder::new(&offer).video_codec(VideoCodec::Vp9).video_direction(MediaDirection::JANUS_SDP_RECVONLY);
+        assert_eq!(answer.build().unwrap_err(), SdpGenerateError::Failed);
+    }
+}
@@ -0,0 +1,297 @@
+/// Working out what changed between two SDPs, e.g. between a client's offer and its renegotiated offer mid-call.
+use super::{Attribute, FmtpParameters, MLine, MediaDirection, MediaType, Sdp};
+
+/// An ICE username fragment and password.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct IceCredentials {
+    pub ufrag: Option<String>,
+    pub pwd: Option<String>,
+}
+
+impl IceCredentials {
+    fn from_attributes<'a>(attributes: impl Iterator<Item = &'a Attribute>) -> Self {
+        let mut result = Self::default();
+        for attr in attributes {
+            let field = match attr.name() {
+                "ice-ufrag" => &mut result.ufrag,
+                "ice-pwd" => &mut result.pwd,
+                _ => continue,
+            };
+            if field.is_none() {
+                *field = attr.value().map(str::to_owned);
+            }
+        }
+        result
+    }
+
+    /// The credentials which apply to the media: its own, or the session-level ones if it has none.
+    fn for_media(sdp: &Sdp, mline: &MLine) -> Self {
+        let session = Self::from_attributes(sdp.attributes());
+        let media = Self::from_attributes(mline.attributes());
+        Self { ufrag: media.ufrag.or(session.ufrag), pwd: media.pwd.or(session.pwd) }
+    }
+}
+
+/// A codec offered in a media, e.g. H.264 with packetization mode 1 as payload type 102.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaCodec {
+    /// The name Janus uses for the codec, e.g. "h264".
+    pub name: &'static str,
+    pub payload_type: i32,
+    /// The `a=fmtp` parameters which tell variants of the codec apart, e.g. H.264's `profile-level-id` and
+    /// `packetization-mode`, or VP9's `profile-id`. Parameters which only tune the codec, like Opus's `useinbandfec`,
+    /// are left out.
+    pub params: FmtpParameters,
+}
+
+impl MediaCodec {
+    /// The names of the parameters which tell variants of the named codec apart.
+    fn identifying_parameters(name: &str) -> &'static [&'static str] {
+        match name {
+            "h264" => &["profile-level-id", "packetization-mode"],
+            "vp9" => &["profile-id"],
+            "av1" => &["profile", "level-idx", "tier"],
+            "h265" => &["profile-id", "tier-flag", "level-id"],
+            _ => &[],
+        }
+    }
+
+    fn from_mline(mline: &MLine, pt: i32) -> Option<Self> {
+        let name = mline.codec_name(pt)?;
+        let fmtp = mline.fmtp(pt).unwrap_or_default();
+        let mut params = FmtpParameters::new();
+        for &param in Self::identifying_parameters(name) {
+            if let Some(value) = fmtp.get(param) {
+                params.set(param, value.to_ascii_lowercase());
+            }
+        }
+        Some(Self { name, payload_type: pt, params })
+    }
+}
+
+/// A change to the session as a whole.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionChange {
+    /// The session-level ICE credentials changed, i.e. ICE is restarting for media without credentials of their own.
+    IceCredentials { from: IceCredentials, to: IceCredentials },
+}
+
+/// A change to one media.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MediaChange {
+    /// The media is new.
+    Added,
+    /// The media is gone entirely. (Peers are meant to reject media rather than remove it.)
+    Removed,
+    /// The kind of media changed, i.e. a rejected m-line was reused for something else.
+    MediaType { from: MediaType, to: MediaType },
+    /// The media was rejected (port 0) or is no longer rejected.
+    Rejected(bool),
+    /// The direction of the media changed, e.g. from sendrecv to recvonly when the client mutes its camera.
+    Direction { from: MediaDirection, to: MediaDirection },
+    /// The codecs, in order of preference, changed: codecs were added or removed, or their payload types or the
+    /// parameters which tell their variants apart changed. Codecs Janus doesn't know, like RTX, aren't listed.
+    Codecs { from: Vec<MediaCodec>, to: Vec<MediaCodec> },
+    /// The ICE credentials which apply to the media changed, i.e. ICE is restarting.
+    IceCredentials { from: IceCredentials, to: IceCredentials },
+}
+
+/// The changes to one media, which is identified by its position in the SDP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MLineDiff {
+    pub index: usize,
+    /// The `a=mid` of the media in the newer SDP, or in the older one if it was removed.
+    pub mid: Option<String>,
+    pub changes: Vec<MediaChange>,
+}
+
+/// The differences between two SDPs. Media are matched up by position, since renegotiation keeps m-lines in order.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SdpDiff {
+    pub session: Vec<SessionChange>,
+    /// The media which changed, in order.
+    pub mlines: Vec<MLineDiff>,
+}
+
+impl SdpDiff {
+    /// Whether nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.session.is_empty() && self.mlines.is_empty()
+    }
+
+    /// Whether the ICE credentials of any media changed.
+    pub fn is_ice_restart(&self) -> bool {
+        self.mlines.iter().flat_map(|m| &m.changes).any(|c| matches!(c, MediaChange::IceCredentials { .. }))
+    }
+}
+
+fn mid(mline: &MLine) -> Option<String> {
+    mline.attribute("mid").and_then(|a| a.value()).map(str::to_owned)
+}
+
+fn codecs(mline: &MLine) -> Vec<MediaCodec> {
+    mline.payload_types().filter_map(|pt| MediaCodec::from_mline(mline, pt)).collect()
+}
+
+fn diff_mline(old_sdp: &Sdp, old: &MLine, new_sdp: &Sdp, new: &MLine) -> Vec<MediaChange> {
+    let mut changes = Vec::new();
+    if old.media_type() != new.media_type() {
+        changes.push(MediaChange::MediaType { from: old.media_type(), to: new.media_type() });
+    }
+    if (old.port() == 0) != (new.port() == 0) {
+        changes.push(MediaChange::Rejected(new.port() == 0));
+    }
+    if old.direction() != new.direction() {
+        changes.push(MediaChange::Direction { from: old.direction(), to: new.direction() });
+    }
+    let (old_codecs, new_codecs) = (codecs(old), codecs(new));
+    if old_codecs != new_codecs {
+        changes.push(MediaChange::Codecs { from: old_codecs, to: new_codecs });
+    }
+    let (old_ice, new_ice) = (IceCredentials::for_media(old_sdp, old), IceCredentials::for_media(new_sdp, new));
+    if old_ice != new_ice {
+        changes.push(MediaChange::IceCredentials { from: old_ice, to: new_ice });
+    }
+    changes
+}
+
+impl Sdp {
+    /// Works out what changed from this SDP to the other one, at session level and in each media.
+    pub fn diff(&self, other: &Sdp) -> SdpDiff {
+        let mut result = SdpDiff::default();
+        let old_ice = IceCredentials::from_attributes(self.attributes());
+        let new_ice = IceCredentials::from_attributes(other.attributes());
+        if old_ice != new_ice {
+            result.session.push(SessionChange::IceCredentials { from: old_ice, to: new_ice });
+        }
+        let (mut old, mut new) = (self.mlines(), other.mlines());
+        for index in 0.. {
+            let (mid, changes) = match (old.next(), new.next()) {
+                (Some(o), Some(n)) => (mid(n), diff_mline(self, o, other, n)),
+                (None, Some(n)) => (mid(n), vec![MediaChange::Added]),
+                (Some(o), None) => (mid(o), vec![MediaChange::Removed]),
+                (None, None) => break,
+            };
+            if !changes.is_empty() {
+                result.mlines.push(MLineDiff { index, mid, changes });
+            }
+        }
+        result
+    }
+}
+
+#[cfg(all(test, feature = "rust-sdp"))]
+mod tests {
+
+    use super::*;
+    use std::ffi::CString;
+
+    fn parse(sdp: &str) -> Sdp {
+        Sdp::parse(&CString::new(sdp).unwrap()).unwrap()
+    }
+
+    fn without_params(codecs: &[(&'static str, i32)]) -> Vec<MediaCodec> {
+        codecs.iter().map(|&(name, payload_type)| MediaCodec { name, payload_type, params: FmtpParameters::new() }).collect()
+    }
+
+    fn codec_changes(old: &Sdp, new: &Sdp) -> Vec<(Vec<MediaCodec>, Vec<MediaCodec>)> {
+        let changes = old.diff(new).mlines.into_iter().flat_map(|m| m.changes);
+        changes
+            .filter_map(|c| match c {
+                MediaChange::Codecs { from, to } => Some((from, to)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn diff_renegotiation() {
+        let offer = parse(include_str!("testdata/chrome.sdp"));
+        assert!(offer.diff(&parse(include_str!("testdata/chrome.sdp"))).is_empty());
+
+        let mut renegotiated = parse(include_str!("testdata/chrome.sdp"));
+        renegotiated.add_session_attribute("ice-ufrag", Some("Rs9t"));
+        let mut mlines = renegotiated.mlines_mut();
+        let audio = mlines.next().unwrap();
+        audio.set_direction(MediaDirection::JANUS_SDP_RECVONLY);
+        audio.retain_attributes(|a| a.name() != "ice-ufrag");
+        audio.add_attribute("ice-ufrag", Some("Ab12"));
+        mlines.next().unwrap().set_port(0);
+        drop(mlines);
+        renegotiated.retain_codecs(&[crate::sdp::AudioCodec::Opus]);
+
+        let diff = offer.diff(&renegotiated);
+        let session_ice = IceCredentials { ufrag: Some("Rs9t".into()), pwd: None };
+        assert_eq!(diff.session, vec![SessionChange::IceCredentials { from: IceCredentials::default(), to: session_ice }]);
+        assert_eq!(diff.mlines.iter().map(|m| (m.index, m.mid.as_deref())).collect::<Vec<_>>(), vec![(0, Some("0")), (1, Some("1"))]);
+        let pwd = Some("9Kq2lW7bFz3Hc1mN8pR4tV6x".to_owned());
+        assert_eq!(
+            diff.mlines[0].changes,
+            vec![
+                MediaChange::Direction { from: MediaDirection::JANUS_SDP_SENDRECV, to: MediaDirection::JANUS_SDP_RECVONLY },
+                MediaChange::Codecs {
+                    from: without_params(&[("opus", 111), ("g722", 9), ("pcmu", 0), ("pcma", 8), ("dtmf", 110), ("dtmf", 126)]),
+                    to: without_params(&[("opus", 111), ("dtmf", 110), ("dtmf", 126)]),
+                },
+                MediaChange::IceCredentials {
+                    from: IceCredentials { ufrag: Some("Xy7k".into()), pwd: pwd.clone() },
+                    to: IceCredentials { ufrag: Some("Ab12".into()), pwd },
+                },
+            ]
+        );
+        assert_eq!(diff.mlines[1].changes, vec![MediaChange::Rejected(true)]);
+        assert!(diff.is_ice_restart());
+    }
+
+    #[test]
+    fn diff_added_and_rejected_media() {
+        let firefox = parse(include_str!("testdata/firefox.sdp"));
+        let chrome = parse(include_str!("testdata/chrome.sdp"));
+        let diff = chrome.diff(&firefox);
+        let third = diff.mlines.iter().find(|m| m.index == 2).unwrap();
+        assert!(third.changes.contains(&MediaChange::MediaType { from: MediaType::JANUS_SDP_APPLICATION, to: MediaType::JANUS_SDP_VIDEO }));
+        assert!(third.changes.contains(&MediaChange::Rejected(true)));
+        let diff = firefox.diff(&chrome);
+        assert!(diff.mlines.iter().all(|m| m.changes != [MediaChange::Added]));
+        let fewer = parse(include_str!("testdata/simulcast.sdp"));
+        assert_eq!((fewer.mlines().count(), chrome.mlines().count()), (1, 3));
+        let diff = fewer.diff(&chrome);
+        let added = diff.mlines.iter().filter(|m| m.changes == [MediaChange::Added]).map(|m| m.index).collect::<Vec<_>>();
+        assert_eq!(added, vec![1, 2]);
+        let diff = chrome.diff(&fewer);
+        let removed = diff.mlines.iter().filter(|m| m.changes == [MediaChange::Removed]).count();
+        assert_eq!(removed, 2);
+    }
+
+    #[test]
+    fn diff_codec_variants() {
+        let offer = parse(include_str!("testdata/chrome.sdp"));
+        let video = codecs(offer.mlines().nth(1).unwrap());
+        assert_eq!(video[0], MediaCodec { name: "vp8", payload_type: 96, params: FmtpParameters::new() });
+        let h264 = video.iter().find(|c| c.payload_type == 102).unwrap();
+        assert_eq!(h264.params.to_string(), "profile-level-id=42001f;packetization-mode=1");
+
+        let mut tuned = parse(include_str!("testdata/chrome.sdp"));
+        tuned.update_codec_parameters(111, |p: &mut crate::sdp::OpusParameters| p.useinbandfec = Some(false));
+        assert!(offer.diff(&tuned).is_empty());
+
+        let mut renumbered = parse(include_str!("testdata/chrome.sdp"));
+        renumbered.rewrite_payload_types(&[(96, 120)].iter().cloned().collect());
+        let changes = codec_changes(&offer, &renumbered);
+        assert_eq!(changes.len(), 1);
+        assert_eq!((changes[0].0[0].payload_type, changes[0].1[0].payload_type), (96, 120));
+
+        let mut reprofiled = parse(include_str!("testdata/chrome.sdp"));
+        let video = reprofiled.mlines_mut().nth(1).unwrap();
+        let mut fmtp = video.fmtp(102).unwrap();
+        fmtp.set("profile-level-id", "640c1f");
+        video.set_fmtp(102, &fmtp);
+        let changes = codec_changes(&offer, &reprofiled);
+        assert_eq!(changes.len(), 1);
+        let profile = |codecs: &[MediaCodec]| {
+            let h264 = codecs.iter().find(|c| c.payload_type == 102).unwrap();
+            h264.params.get("profile-level-id").map(str::to_owned)
+        };
+        assert_eq!((profile(&changes[0].0), profile(&changes[0].1)), (Some("42001f".to_owned()), Some("640c1f".to_owned())));
+    }
+}
@@ -0,0 +1,210 @@
+/// RTP header extension mappings, i.e. `a=extmap` attributes, and translating their IDs between peers.
+use std::collections::HashMap;
+use std::fmt;
+use super::{Attribute, MLine, MediaDirection, Sdp};
+
+/// An RTP header extension mapping (RFC 8285), e.g. `a=extmap:2/recvonly urn:ietf:params:rtp-hdrext:csrc-audio-level`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extmap {
+    pub id: u16,
+    /// The direction the extension is used in, or JANUS_SDP_DEFAULT if it isn't specific to one.
+    pub direction: MediaDirection,
+    pub uri: String,
+    /// Any extension-specific attributes after the URI.
+    pub attributes: Option<String>,
+}
+
+impl Extmap {
+    /// Creates a mapping of the given ID to the given extension URI, in either direction.
+    pub fn new(id: u16, uri: &str) -> Self {
+        Self { id, direction: MediaDirection::JANUS_SDP_DEFAULT, uri: uri.to_owned(), attributes: None }
+    }
+
+    /// Parses the value of an `a=extmap` attribute.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut tokens = value.splitn(3, ' ');
+        let first = tokens.next()?;
+        let (id, direction) = match first.split_once('/') {
+            Some((id, direction)) => (id, parse_direction(direction)?),
+            None => (first, MediaDirection::JANUS_SDP_DEFAULT),
+        };
+        let uri = tokens.next().filter(|u| !u.is_empty())?.to_owned();
+        let attributes = tokens.next().map(str::to_owned);
+        Some(Self { id: id.parse().ok()?, direction, uri, attributes })
+    }
+}
+
+impl fmt::Display for Extmap {
+    /// Writes the value of the `a=extmap` attribute for this mapping.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.id)?;
+        if let Some(direction) = direction_str(self.direction) {
+            write!(f, "/{}", direction)?;
+        }
+        write!(f, " {}", self.uri)?;
+        if let Some(ref attributes) = self.attributes {
+            write!(f, " {}", attributes)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_direction(s: &str) -> Option<MediaDirection> {
+    match s {
+        "sendrecv" => Some(MediaDirection::JANUS_SDP_SENDRECV),
+        "sendonly" => Some(MediaDirection::JANUS_SDP_SENDONLY),
+        "recvonly" => Some(MediaDirection::JANUS_SDP_RECVONLY),
+        "inactive" => Some(MediaDirection::JANUS_SDP_INACTIVE),
+        _ => None,
+    }
+}
+
+fn direction_str(direction: MediaDirection) -> Option<&'static str> {
+    match direction {
+        MediaDirection::JANUS_SDP_SENDRECV => Some("sendrecv"),
+        MediaDirection::JANUS_SDP_SENDONLY => Some("sendonly"),
+        MediaDirection::JANUS_SDP_RECVONLY => Some("recvonly"),
+        MediaDirection::JANUS_SDP_INACTIVE => Some("inactive"),
+        _ => None,
+    }
+}
+
+fn as_extmap(attr: &Attribute) -> Option<Extmap> {
+    if attr.name() == "extmap" {
+        attr.value().and_then(Extmap::parse)
+    } else {
+        None
+    }
+}
+
+/// Rewrites the IDs of any extmaps in the attributes according to the mapping, all at once, so that swapping two IDs
+/// works as expected.
+fn remap_ids<'a>(attributes: impl Iterator<Item = &'a mut Attribute>, mapping: &HashMap<u16, u16>) {
+    for attr in attributes {
+        if let Some(mut extmap) = as_extmap(attr) {
+            if let Some(&to) = mapping.get(&extmap.id) {
+                extmap.id = to;
+                attr.set_value(Some(&extmap.to_string()));
+            }
+        }
+    }
+}
+
+impl MLine {
+    /// The RTP header extensions mapped for this media, in order.
+    pub fn extmaps(&self) -> impl Iterator<Item = Extmap> + '_ {
+        self.attributes().filter_map(as_extmap)
+    }
+
+    /// Adds a mapping for an RTP header extension to this media.
+    pub fn add_extmap(&mut self, extmap: &Extmap) {
+        self.add_attribute("extmap", Some(&extmap.to_string()));
+    }
+
+    /// Removes any mapping for the RTP header extension with the given URI from this media.
+    pub fn remove_extmap(&mut self, uri: &str) {
+        self.retain_attributes(|a| !matches!(as_extmap(a), Some(e) if e.uri == uri));
+    }
+
+    /// Changes the IDs of the RTP header extensions mapped for this media from the keys of the mapping to the values.
+    pub fn remap_extmap_ids(&mut self, mapping: &HashMap<u16, u16>) {
+        remap_ids(self.attributes_mut(), mapping);
+    }
+}
+
+impl Sdp {
+    /// The RTP header extensions mapped in this SDP, at session level or in any media, in order.
+    pub fn extmaps(&self) -> Vec<Extmap> {
+        let session = self.attributes().filter_map(as_extmap);
+        session.chain(self.mlines().flat_map(MLine::extmaps)).collect()
+    }
+
+    /// The ID mapped to the RTP header extension with the given URI, if any.
+    pub fn extmap_id(&self, uri: &str) -> Option<u16> {
+        self.extmaps().into_iter().find(|e| e.uri == uri).map(|e| e.id)
+    }
+
+    /// Removes any mapping for the RTP header extension with the given URI, at session level and in every media.
+    pub fn remove_extmap(&mut self, uri: &str) {
+        self.retain_session_attributes(|a| !matches!(as_extmap(a), Some(e) if e.uri == uri));
+        for mline in self.mlines_mut() {
+            mline.remove_extmap(uri);
+        }
+    }
+
+    /// Changes the IDs of the RTP header extensions mapped in this SDP from the keys of the mapping to the values, at
+    /// session level and in every media. Every ID is rewritten at once, so mappings can swap IDs around.
+    pub fn remap_extmap_ids(&mut self, mapping: &HashMap<u16, u16>) {
+        remap_ids(self.attributes_mut(), mapping);
+        for mline in self.mlines_mut() {
+            mline.remap_extmap_ids(mapping);
+        }
+    }
+
+    /// Builds a mapping from the IDs in this SDP to the IDs which the other SDP uses for the same extensions, for use
+    /// with `remap_extmap_ids`, e.g. to make a subscriber's IDs line up with a publisher's. Extensions which the other
+    /// SDP doesn't have are left out.
+    pub fn extmap_mapping_to(&self, other: &Sdp) -> HashMap<u16, u16> {
+        let theirs = other.extmaps().into_iter().map(|e| (e.uri, e.id)).collect::<HashMap<_, _>>();
+        self.extmaps()
+            .into_iter()
+            .filter_map(|e| theirs.get(&e.uri).map(|&to| (e.id, to)))
+            .filter(|(from, to)| from != to)
+            .collect()
+    }
+}
+
+#[cfg(all(test, feature = "rust-sdp"))]
+mod tests {
+
+    use super::*;
+    use std::ffi::CString;
+
+    fn parse(sdp: &str) -> Sdp {
+        Sdp::parse(&CString::new(sdp).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn parse_extmaps() {
+        let extmap = Extmap::parse("2/recvonly urn:ietf:params:rtp-hdrext:csrc-audio-level").unwrap();
+        assert_eq!(extmap.id, 2);
+        assert_eq!(extmap.direction, MediaDirection::JANUS_SDP_RECVONLY);
+        assert_eq!(extmap.uri, "urn:ietf:params:rtp-hdrext:csrc-audio-level");
+        assert_eq!(extmap.to_string(), "2/recvonly urn:ietf:params:rtp-hdrext:csrc-audio-level");
+        let extmap = Extmap::parse("7 urn:ietf:params:rtp-hdrext:encrypt urn:ietf:params:rtp-hdrext:toffset").unwrap();
+        assert_eq!(extmap.attributes.as_deref(), Some("urn:ietf:params:rtp-hdrext:toffset"));
+        assert_eq!(Extmap::parse("1"), None);
+        assert_eq!(Extmap::parse("x urn:foo"), None);
+        assert_eq!(Extmap::parse("1/sideways urn:foo"), None);
+
+        let sdp = parse(include_str!("testdata/firefox.sdp"));
+        let audio = sdp.mlines().next().unwrap().extmaps().collect::<Vec<_>>();
+        assert_eq!(audio.iter().map(|e| e.id).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(sdp.extmap_id("urn:ietf:params:rtp-hdrext:toffset"), Some(5));
+    }
+
+    #[test]
+    fn remap_extmaps() {
+        let chrome = parse(include_str!("testdata/chrome.sdp"));
+        let mut firefox = parse(include_str!("testdata/firefox.sdp"));
+        let mapping = firefox.extmap_mapping_to(&chrome);
+        assert_eq!(mapping.get(&3), Some(&4)); // sdes:mid
+        assert_eq!(mapping.get(&5), Some(&14)); // toffset
+        assert_eq!(mapping.get(&1), None); // audio level has the same ID
+        firefox.remap_extmap_ids(&mapping);
+        for extmap in firefox.extmaps() {
+            assert_eq!(chrome.extmap_id(&extmap.uri).unwrap_or(extmap.id), extmap.id);
+        }
+
+        let mut sdp = parse(include_str!("testdata/chrome.sdp"));
+        sdp.remap_extmap_ids(&[(1, 2), (2, 1)].iter().cloned().collect());
+        assert_eq!(sdp.extmap_id("urn:ietf:params:rtp-hdrext:ssrc-audio-level"), Some(2));
+        assert_eq!(sdp.extmap_id("http://www.webrtc.org/experiments/rtp-hdrext/abs-send-time"), Some(1));
+
+        sdp.remove_extmap("urn:ietf:params:rtp-hdrext:sdes:mid");
+        assert_eq!(sdp.extmap_id("urn:ietf:params:rtp-hdrext:sdes:mid"), None);
+        let video = sdp.mlines_mut().nth(1).unwrap();
+        video.add_extmap(&Extmap::new(9, "urn:ietf:params:rtp-hdrext:sdes:mid"));
+        assert_eq!(video.extmaps().last(), Some(Extmap::new(9, "urn:ietf:params:rtp-hdrext:sdes:mid")));
+    }
+}
@@ -0,0 +1,293 @@
+/// Typed access to the codec parameters in `a=fmtp` attributes.
+use std::fmt;
+use std::str::FromStr;
+use std::convert::Infallible;
+use super::{MLine, Sdp};
+
+/// The parameters in an `a=fmtp` attribute, e.g. `minptime=10;useinbandfec=1`, in order. Parameters without a value,
+/// like the `0-16` of telephone-event, have a value of None.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FmtpParameters(Vec<(String, Option<String>)>);
+
+impl FmtpParameters {
+    /// Creates an empty parameter list.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The value of the named parameter, if it's present and has one. Names are case-insensitive.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.iter().find(|(k, _)| k.eq_ignore_ascii_case(name)).and_then(|(_, v)| v.as_deref())
+    }
+
+    /// Whether the named parameter is present.
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.iter().any(|(k, _)| k.eq_ignore_ascii_case(name))
+    }
+
+    /// Sets the named parameter, replacing it in place if it's already present and appending it otherwise.
+    pub fn set(&mut self, name: &str, value: impl ToString) {
+        let value = Some(value.to_string());
+        match self.0.iter_mut().find(|(k, _)| k.eq_ignore_ascii_case(name)) {
+            Some(param) => param.1 = value,
+            None => self.0.push((name.to_owned(), value)),
+        }
+    }
+
+    /// Removes the named parameter.
+    pub fn remove(&mut self, name: &str) {
+        self.0.retain(|(k, _)| !k.eq_ignore_ascii_case(name));
+    }
+
+    /// Whether there are no parameters.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The parameters, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_deref()))
+    }
+
+    fn get_parsed<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.get(name).and_then(|v| v.parse().ok())
+    }
+
+    fn get_flag(&self, name: &str) -> Option<bool> {
+        self.get_parsed::<u8>(name).map(|v| v != 0)
+    }
+
+    fn set_or_remove(&mut self, name: &str, value: Option<impl ToString>) {
+        match value {
+            Some(v) => self.set(name, v),
+            None => self.remove(name),
+        }
+    }
+}
+
+impl FromStr for FmtpParameters {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Infallible> {
+        Ok(Self(
+            s.split(';')
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(|p| match p.split_once('=') {
+                    Some((k, v)) => (k.trim().to_owned(), Some(v.trim().to_owned())),
+                    None => (p.to_owned(), None),
+                })
+                .collect(),
+        ))
+    }
+}
+
+impl fmt::Display for FmtpParameters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (k, v)) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(";")?;
+            }
+            match v {
+                Some(v) => write!(f, "{}={}", k, v)?,
+                None => f.write_str(k)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The known parameters of a particular codec. Reading and then writing back leaves other parameters alone; writing a
+/// field of None removes that parameter.
+pub trait CodecParameters: Sized {
+    /// Reads the known parameters.
+    fn from_fmtp(params: &FmtpParameters) -> Self;
+
+    /// Writes the known parameters into the list.
+    fn write_fmtp(&self, params: &mut FmtpParameters);
+}
+
+/// Opus parameters (RFC 7587.)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OpusParameters {
+    pub stereo: Option<bool>,
+    pub useinbandfec: Option<bool>,
+    pub maxaveragebitrate: Option<u32>,
+    pub usedtx: Option<bool>,
+}
+
+impl CodecParameters for OpusParameters {
+    fn from_fmtp(params: &FmtpParameters) -> Self {
+        Self {
+            stereo: params.get_flag("stereo"),
+            useinbandfec: params.get_flag("useinbandfec"),
+            maxaveragebitrate: params.get_parsed("maxaveragebitrate"),
+            usedtx: params.get_flag("usedtx"),
+        }
+    }
+
+    fn write_fmtp(&self, params: &mut FmtpParameters) {
+        params.set_or_remove("stereo", self.stereo.map(u8::from));
+        params.set_or_remove("useinbandfec", self.useinbandfec.map(u8::from));
+        params.set_or_remove("maxaveragebitrate", self.maxaveragebitrate);
+        params.set_or_remove("usedtx", self.usedtx.map(u8::from));
+    }
+}
+
+/// H.264 parameters (RFC 6184.)
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct H264Parameters {
+    pub profile_level_id: Option<String>,
+    pub packetization_mode: Option<u8>,
+}
+
+impl CodecParameters for H264Parameters {
+    fn from_fmtp(params: &FmtpParameters) -> Self {
+        Self {
+            profile_level_id: params.get("profile-level-id").map(str::to_owned),
+            packetization_mode: params.get_parsed("packetization-mode"),
+        }
+    }
+
+    fn write_fmtp(&self, params: &mut FmtpParameters) {
+        params.set_or_remove("profile-level-id", self.profile_level_id.as_ref());
+        params.set_or_remove("packetization-mode", self.packetization_mode);
+    }
+}
+
+/// VP9 parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Vp9Parameters {
+    pub profile_id: Option<u8>,
+}
+
+impl CodecParameters for Vp9Parameters {
+    fn from_fmtp(params: &FmtpParameters) -> Self {
+        Self { profile_id: params.get_parsed("profile-id") }
+    }
+
+    fn write_fmtp(&self, params: &mut FmtpParameters) {
+        params.set_or_remove("profile-id", self.profile_id);
+    }
+}
+
+/// RTX parameters (RFC 4588), i.e. the payload type being retransmitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RtxParameters {
+    pub apt: Option<i32>,
+}
+
+impl CodecParameters for RtxParameters {
+    fn from_fmtp(params: &FmtpParameters) -> Self {
+        Self { apt: params.get_parsed("apt") }
+    }
+
+    fn write_fmtp(&self, params: &mut FmtpParameters) {
+        params.set_or_remove("apt", self.apt);
+    }
+}
+
+/// Splits an `a=fmtp` value into its payload type and parameters.
+fn split_fmtp(value: &str) -> Option<(i32, &str)> {
+    let (pt, params) = value.split_once(' ').unwrap_or((value, ""));
+    Some((pt.parse().ok()?, params))
+}
+
+impl MLine {
+    /// The parameters in the `a=fmtp` attribute for the given payload type, if there is one.
+    pub fn fmtp(&self, pt: i32) -> Option<FmtpParameters> {
+        self.attributes()
+            .filter(|a| a.name() == "fmtp")
+            .filter_map(|a| a.value().and_then(split_fmtp))
+            .find(|(p, _)| *p == pt)
+            .map(|(_, params)| params.parse().unwrap())
+    }
+
+    /// Replaces the `a=fmtp` attribute for the given payload type, adding one if there isn't one already, or removing
+    /// it if there are no parameters.
+    ///
+    /// Panics if the parameters contain a nul byte.
+    pub fn set_fmtp(&mut self, pt: i32, params: &FmtpParameters) {
+        let is_fmtp = |a: &super::Attribute| {
+            a.name() == "fmtp" && matches!(a.value().and_then(split_fmtp), Some((p, _)) if p == pt)
+        };
+        if params.is_empty() {
+            return self.retain_attributes(|a| !is_fmtp(a));
+        }
+        let value = format!("{} {}", pt, params);
+        if self.attributes().any(is_fmtp) {
+            self.attributes_mut().filter(|a| is_fmtp(a)).for_each(|a| a.set_value(Some(&value)));
+        } else {
+            self.add_attribute("fmtp", Some(&value));
+        }
+    }
+
+    /// The known parameters of the codec with the given payload type. They're all None if there's no `a=fmtp`.
+    pub fn codec_parameters<T: CodecParameters>(&self, pt: i32) -> T {
+        T::from_fmtp(&self.fmtp(pt).unwrap_or_default())
+    }
+
+    /// Edits the known parameters of the codec with the given payload type and writes them back to the `a=fmtp`.
+    pub fn update_codec_parameters<T: CodecParameters, F: FnOnce(&mut T)>(&mut self, pt: i32, f: F) {
+        let mut params = self.fmtp(pt).unwrap_or_default();
+        let mut typed = T::from_fmtp(&params);
+        f(&mut typed);
+        typed.write_fmtp(&mut params);
+        self.set_fmtp(pt, &params);
+    }
+}
+
+impl Sdp {
+    /// Edits the known parameters of the codec with the given payload type in every m-line which lists it, e.g.
+    /// `sdp.update_codec_parameters(pt, |p: &mut OpusParameters| p.useinbandfec = Some(true))` to force FEC.
+    pub fn update_codec_parameters<T: CodecParameters, F: FnMut(&mut T)>(&mut self, pt: i32, mut f: F) {
+        for mline in self.mlines_mut().filter(|m| m.payload_types().any(|p| p == pt)) {
+            mline.update_codec_parameters(pt, &mut f);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "rust-sdp"))]
+mod tests {
+
+    use super::*;
+    use crate::sdp::{AudioCodec, MediaType};
+    use std::ffi::CString;
+
+    #[test]
+    fn parse_and_write_parameters() {
+        let params = "maxplaybackrate=48000; stereo=1;useinbandfec=1".parse::<FmtpParameters>().unwrap();
+        assert_eq!(params.get("STEREO"), Some("1"));
+        assert_eq!(params.to_string(), "maxplaybackrate=48000;stereo=1;useinbandfec=1");
+        let events = "0-16".parse::<FmtpParameters>().unwrap();
+        assert!(events.contains("0-16"));
+        assert_eq!(events.to_string(), "0-16");
+
+        let mut params = params;
+        let mut opus = OpusParameters::from_fmtp(&params);
+        assert_eq!(opus, OpusParameters { stereo: Some(true), useinbandfec: Some(true), ..Default::default() });
+        opus.stereo = None;
+        opus.maxaveragebitrate = Some(32000);
+        opus.write_fmtp(&mut params);
+        assert_eq!(params.to_string(), "maxplaybackrate=48000;useinbandfec=1;maxaveragebitrate=32000");
+    }
+
+    #[test]
+    fn edit_codec_parameters() {
+        let mut sdp = Sdp::parse(&CString::new(include_str!("testdata/chrome.sdp")).unwrap()).unwrap();
+        let video = sdp.mlines().find(|m| m.media_type() == MediaType::JANUS_SDP_VIDEO).unwrap();
+        assert_eq!(video.codec_parameters::<Vp9Parameters>(100).profile_id, Some(2));
+        assert_eq!(video.codec_parameters::<RtxParameters>(101).apt, Some(100));
+        let h264 = video.codec_parameters::<H264Parameters>(45);
+        assert_eq!((h264.profile_level_id.as_deref(), h264.packetization_mode), (Some("42e01f"), Some(1)));
+        assert_eq!(video.codec_parameters::<RtxParameters>(96), RtxParameters::default());
+
+        let opus = sdp.get_payload_type(AudioCodec::Opus.to_cstr()).unwrap();
+        sdp.update_codec_parameters(opus, |p: &mut OpusParameters| p.maxaveragebitrate = Some(20000));
+        sdp.update_codec_parameters(0, |p: &mut OpusParameters| p.usedtx = Some(true));
+        let audio = sdp.mlines().find(|m| m.media_type() == MediaType::JANUS_SDP_AUDIO).unwrap();
+        assert_eq!(audio.fmtp(opus).unwrap().to_string(), "minptime=10;useinbandfec=1;maxaveragebitrate=20000");
+        assert_eq!(audio.fmtp(0).unwrap().to_string(), "usedtx=1");
+        assert_eq!(audio.attributes().filter(|a| a.name() == "fmtp").count(), 3);
+    }
+}
@@ -0,0 +1,68 @@
+/// The SDP backend which uses Janus's own SDP parsing machinery, via libjanus.
+use janus_plugin_sys as ffi;
+use libc;
+use std::ffi::CStr;
+use std::os::raw::c_int;
+use std::ptr;
+use crate::utils::GLibString;
+use super::{RawAttribute, RawSdp, Sdp, SdpParseError};
+
+pub(super) unsafe fn parse(offer: &CStr) -> Result<*mut RawSdp, SdpParseError> {
+    // libjanus accepts arbitrary bytes, but everything reading the parsed SDP expects UTF-8
+    if offer.to_str().is_err() {
+        return Err(SdpParseError { buffer: b"Invalid SDP (not UTF-8)".to_vec() });
+    }
+    let mut error_buffer = Vec::with_capacity(512);
+    let error_ptr = error_buffer.as_mut_ptr() as *mut _;
+    let result = ffi::sdp::janus_sdp_parse(offer.as_ptr(), error_ptr, error_buffer.capacity());
+    if result.is_null() {
+        error_buffer.set_len(libc::strlen(error_ptr));
+        Err(SdpParseError { buffer: error_buffer })
+    } else {
+        Ok(result)
+    }
+}
+
+pub(super) unsafe fn write(sdp: *mut RawSdp) -> GLibString {
+    GLibString::from_chars(ffi::sdp::janus_sdp_write(sdp)).expect("Mysterious error writing SDP to string :(")
+}
+
+pub(super) unsafe fn destroy(sdp: *mut RawSdp) {
+    ffi::sdp::janus_sdp_destroy(sdp);
+}
+
+pub(super) unsafe fn create_attribute(name: &CStr, value: Option<&CStr>) -> *mut RawAttribute {
+    match value {
+        // the value is a printf format string, so don't let any % in the value be interpreted
+        Some(v) => ffi::sdp::janus_sdp_attribute_create(name.as_ptr(), b"%s\0".as_ptr() as *const _, v.as_ptr()),
+        None => ffi::sdp::janus_sdp_attribute_create(name.as_ptr(), ptr::null()),
+    }
+}
+
+pub(super) unsafe fn destroy_attribute(attr: *mut RawAttribute) {
+    ffi::sdp::janus_sdp_attribute_destroy(attr);
+}
+
+pub(super) unsafe fn get_codec_pt(sdp: *mut RawSdp, codec: &CStr, profile: Option<&CStr>) -> c_int {
+    match profile {
+        Some(p) => ffi::sdp::janus_sdp_get_codec_pt_full(sdp, codec.as_ptr(), p.as_ptr()),
+        None => ffi::sdp::janus_sdp_get_codec_pt(sdp, codec.as_ptr()),
+    }
+}
+
+pub(super) unsafe fn get_codec_name(sdp: *mut RawSdp, pt: c_int) -> Option<&'static str> {
+    // the names are static strings in libjanus
+    let name = ffi::sdp::janus_sdp_get_codec_name(sdp, pt);
+    name.as_ref().map(|n| CStr::from_ptr(n).to_str().expect("Invalid UTF-8 in codec name :("))
+}
+
+/// Calls a libjanus function which takes a Janus SDP.
+pub(super) unsafe fn with_janus_sdp<R>(sdp: &Sdp, f: impl FnOnce(*mut RawSdp) -> R) -> R {
+    f(sdp.ptr)
+}
+
+/// Takes ownership of an SDP which libjanus returned.
+pub(super) unsafe fn adopt_janus_sdp(ptr: *mut RawSdp) -> Option<Sdp> {
+    Sdp::new(ptr)
+}
+
@@ -0,0 +1,278 @@
+/// Safe views over the media lines and attributes of a parsed SDP.
+use glib_sys as glib;
+use std::ffi::{CStr, CString};
+use std::marker::PhantomData;
+use std::os::raw::{c_char, c_int, c_short};
+use std::ptr;
+use super::{backend, MediaDirection, MediaType, RawAttribute, RawMLine};
+
+/// Iterates over the data pointers in a `GList`.
+pub(super) struct GListData<'a> {
+    node: *mut glib::GList,
+    _list: PhantomData<&'a glib::GList>,
+}
+
+impl<'a> GListData<'a> {
+    /// Iterates over the given list, which must stay unchanged for the lifetime of the iterator.
+    pub(super) unsafe fn new(list: *mut glib::GList) -> Self {
+        Self { node: list, _list: PhantomData }
+    }
+}
+
+impl<'a> Iterator for GListData<'a> {
+    type Item = glib::gpointer;
+
+    fn next(&mut self) -> Option<glib::gpointer> {
+        unsafe {
+            self.node.as_ref().map(|node| {
+                self.node = node.next;
+                node.data
+            })
+        }
+    }
+}
+
+/// Gets a string field of a Janus SDP structure, or None if it's null or not UTF-8. (Parsing rejects SDPs which aren't
+/// UTF-8, but SDPs taken from raw pointers haven't been checked, and they're from remote peers.)
+pub(super) unsafe fn str_field<'a>(field: *const c_char) -> Option<&'a str> {
+    field.as_ref().and_then(|f| CStr::from_ptr(f).to_str().ok())
+}
+
+/// Replaces a glib-allocated string field of a Janus SDP structure.
+pub(super) unsafe fn set_str_field(field: &mut *mut c_char, value: Option<&str>) {
+    glib::g_free(*field as *mut _);
+    *field = match value {
+        Some(v) => glib::g_strdup(CString::new(v).expect("SDP values can't contain nul bytes.").as_ptr()),
+        None => ptr::null_mut(),
+    };
+}
+
+/// Creates an SDP attribute, with a copy of the given name and value.
+pub(super) unsafe fn create_attribute(name: &str, value: Option<&str>) -> *mut RawAttribute {
+    let name = CString::new(name).expect("SDP attribute names can't contain nul bytes.");
+    let value = value.map(|v| CString::new(v).expect("SDP attribute values can't contain nul bytes."));
+    backend::create_attribute(&name, value.as_deref())
+}
+
+/// Keeps only the attributes in the list which the predicate accepts, releasing the rest.
+pub(super) unsafe fn retain_attributes<F>(list: &mut *mut glib::GList, mut keep: F)
+where
+    F: FnMut(&Attribute) -> bool,
+{
+    let mut node = *list;
+    while let Some(n) = node.as_ref() {
+        let next = n.next; // we might delete this link, so grab next now!
+        let attr = n.data as *mut RawAttribute;
+        if !keep(Attribute::from_raw(&*attr)) {
+            *list = glib::g_list_delete_link(*list, node);
+            backend::destroy_attribute(attr);
+        }
+        node = next;
+    }
+}
+
+/// An SDP attribute, e.g. `a=rtpmap:111 opus/48000/2`, which has the name `rtpmap` and the value `111 opus/48000/2`.
+#[repr(transparent)]
+#[derive(Debug)]
+pub struct Attribute(RawAttribute);
+
+impl Attribute {
+    pub(super) unsafe fn from_raw(raw: &RawAttribute) -> &Self {
+        &*(raw as *const RawAttribute as *const Self)
+    }
+
+    pub(super) unsafe fn from_raw_mut(raw: &mut RawAttribute) -> &mut Self {
+        &mut *(raw as *mut RawAttribute as *mut Self)
+    }
+
+    /// The attribute name, or an empty string if it's missing or not UTF-8.
+    pub fn name(&self) -> &str {
+        unsafe { str_field(self.0.name).unwrap_or("") }
+    }
+
+    /// The attribute value, or None for flag attributes like `a=rtcp-mux` (or if the value isn't UTF-8.)
+    pub fn value(&self) -> Option<&str> {
+        unsafe { str_field(self.0.value) }
+    }
+
+    /// Replaces the attribute value.
+    ///
+    /// Panics if the value contains a nul byte.
+    pub fn set_value(&mut self, value: Option<&str>) {
+        unsafe { set_str_field(&mut self.0.value, value) }
+    }
+
+    /// The direction this attribute applies to, if it's specific to one.
+    pub fn direction(&self) -> MediaDirection {
+        self.0.direction
+    }
+
+    /// Gets the raw Janus attribute.
+    pub fn as_raw(&self) -> &RawAttribute {
+        &self.0
+    }
+}
+
+/// An SDP media line, along with everything that describes it, i.e. its connection, bandwidth and attribute lines.
+#[repr(transparent)]
+#[derive(Debug)]
+pub struct MLine(RawMLine);
+
+impl MLine {
+    pub(super) unsafe fn from_raw(raw: &RawMLine) -> &Self {
+        &*(raw as *const RawMLine as *const Self)
+    }
+
+    pub(super) unsafe fn from_raw_mut(raw: &mut RawMLine) -> &mut Self {
+        &mut *(raw as *mut RawMLine as *mut Self)
+    }
+
+    /// The kind of media, e.g. audio.
+    pub fn media_type(&self) -> MediaType {
+        self.0.type_
+    }
+
+    /// The port; 0 if the media is rejected or disabled.
+    pub fn port(&self) -> u16 {
+        self.0.port as u16
+    }
+
+    /// Sets the port.
+    pub fn set_port(&mut self, port: u16) {
+        self.0.port = port as c_short;
+    }
+
+    /// The transport protocol, e.g. `UDP/TLS/RTP/SAVPF`.
+    pub fn proto(&self) -> Option<&str> {
+        unsafe { str_field(self.0.proto) }
+    }
+
+    /// Sets the transport protocol.
+    ///
+    /// Panics if the protocol contains a nul byte.
+    pub fn set_proto(&mut self, proto: &str) {
+        unsafe { set_str_field(&mut self.0.proto, Some(proto)) }
+    }
+
+    /// The direction of the media, e.g. sendrecv.
+    pub fn direction(&self) -> MediaDirection {
+        self.0.direction
+    }
+
+    /// Sets the direction of the media.
+    pub fn set_direction(&mut self, direction: MediaDirection) {
+        self.0.direction = direction;
+    }
+
+    /// The address in this media's `c=` line, if it has one.
+    pub fn connection_address(&self) -> Option<&str> {
+        unsafe { str_field(self.0.c_addr) }
+    }
+
+    /// Whether the address in this media's `c=` line is an IPv4 address.
+    pub fn connection_is_ipv4(&self) -> bool {
+        self.0.c_ipv4 != 0
+    }
+
+    /// Sets or removes the address in this media's `c=` line. Addresses containing a colon are written as IPv6.
+    ///
+    /// Panics if the address contains a nul byte.
+    pub fn set_connection_address(&mut self, address: Option<&str>) {
+        let ipv4 = match address {
+            Some(a) => !a.contains(':'),
+            None => true,
+        };
+        self.0.c_ipv4 = ipv4 as glib::gboolean;
+        unsafe { set_str_field(&mut self.0.c_addr, address) }
+    }
+
+    /// The bandwidth modifier and value in this media's `b=` line, e.g. `("AS", 256)`, if it has one.
+    pub fn bandwidth(&self) -> Option<(&str, i32)> {
+        unsafe { str_field(self.0.b_name).map(|name| (name, self.0.b_value)) }
+    }
+
+    /// Sets or removes this media's `b=` line.
+    ///
+    /// Panics if the modifier contains a nul byte.
+    pub fn set_bandwidth(&mut self, bandwidth: Option<(&str, i32)>) {
+        unsafe { set_str_field(&mut self.0.b_name, bandwidth.map(|(name, _)| name)) }
+        self.0.b_value = bandwidth.map_or(0, |(_, value)| value);
+    }
+
+    /// The RTP payload types listed in the `m=` line, in order.
+    pub fn payload_types(&self) -> impl Iterator<Item = i32> + '_ {
+        unsafe { GListData::new(self.0.ptypes).map(|pt| pt as usize as c_int) }
+    }
+
+    /// Replaces the payload types listed in the `m=` line. The formats are replaced to match.
+    pub fn set_payload_types(&mut self, pts: &[i32]) {
+        let formats = pts.iter().map(|pt| pt.to_string()).collect::<Vec<_>>();
+        self.set_formats(&formats.iter().map(String::as_str).collect::<Vec<_>>());
+    }
+
+    /// The formats listed in the `m=` line, in order. For RTP media these are the payload types; for data channels,
+    /// something like `webrtc-datachannel`. Formats which aren't UTF-8 are skipped.
+    pub fn formats(&self) -> impl Iterator<Item = &str> + '_ {
+        unsafe { GListData::new(self.0.fmts).filter_map(|f| str_field(f as *const c_char)) }
+    }
+
+    /// Replaces the formats listed in the `m=` line. The payload types are replaced with the numeric formats.
+    ///
+    /// Panics if a format contains a nul byte.
+    pub fn set_formats(&mut self, formats: &[&str]) {
+        unsafe {
+            glib::g_list_free_full(self.0.fmts, Some(glib::g_free));
+            glib::g_list_free(self.0.ptypes);
+            self.0.fmts = ptr::null_mut();
+            self.0.ptypes = ptr::null_mut();
+            for format in formats {
+                let fmt = CString::new(*format).expect("SDP formats can't contain nul bytes.");
+                self.0.fmts = glib::g_list_append(self.0.fmts, glib::g_strdup(fmt.as_ptr()) as *mut _);
+                if let Ok(pt) = format.parse::<c_int>() {
+                    // payload type data in the list is cast to pointers
+                    self.0.ptypes = glib::g_list_append(self.0.ptypes, pt as usize as *mut _);
+                }
+            }
+        }
+    }
+
+    /// The attributes of this media, in order.
+    pub fn attributes(&self) -> impl Iterator<Item = &Attribute> + '_ {
+        unsafe { GListData::new(self.0.attributes).map(|a| Attribute::from_raw(&*(a as *const RawAttribute))) }
+    }
+
+    /// The attributes of this media, in order, for modification.
+    pub fn attributes_mut(&mut self) -> impl Iterator<Item = &mut Attribute> + '_ {
+        unsafe { GListData::new(self.0.attributes).map(|a| Attribute::from_raw_mut(&mut *(a as *mut RawAttribute))) }
+    }
+
+    /// The first attribute with the given name, if any.
+    pub fn attribute(&self, name: &str) -> Option<&Attribute> {
+        self.attributes().find(|a| a.name() == name)
+    }
+
+    /// Appends an attribute to this media.
+    ///
+    /// Panics if the name or value contains a nul byte.
+    pub fn add_attribute(&mut self, name: &str, value: Option<&str>) {
+        unsafe {
+            let attr = create_attribute(name, value);
+            self.0.attributes = glib::g_list_append(self.0.attributes, attr as *mut _);
+        }
+    }
+
+    /// Removes every attribute which the predicate rejects.
+    pub fn retain_attributes<F: FnMut(&Attribute) -> bool>(&mut self, keep: F) {
+        unsafe { retain_attributes(&mut self.0.attributes, keep) }
+    }
+
+    /// Gets the raw Janus m-line.
+    pub fn as_raw(&self) -> &RawMLine {
+        &self.0
+    }
+
+    /// Gets the raw Janus m-line for modification.
+    pub fn as_raw_mut(&mut self) -> &mut RawMLine {
+        &mut self.0
+    }
+}
@@ -0,0 +1,667 @@
+/// A pure-Rust SDP backend, which follows the parsing and writing rules of Janus's sdp-utils.c, so that SDPs can be
+/// manipulated (and tested) without libjanus. SDPs are still represented by the same structures as Janus uses, allocated with glib, but
+/// they're owned entirely by Rust; libjanus functions only ever see copies made by `with_janus_sdp`.
+use glib_sys as glib;
+use std::borrow::Cow;
+use janus_plugin_sys as ffi;
+use std::ffi::{CStr, CString};
+use std::fmt::Write;
+use std::mem;
+use std::os::raw::{c_char, c_int};
+use crate::utils::GLibString;
+use super::mline::GListData;
+use super::{MediaDirection, MediaType, RawAttribute, RawMLine, RawSdp, Sdp, SdpParseError};
+
+unsafe fn alloc<T>() -> *mut T {
+    glib::g_malloc0(mem::size_of::<T>()) as *mut T
+}
+
+unsafe fn dup(s: &str) -> *mut c_char {
+    // everything we copy came out of a C string, so it can't contain a nul
+    glib::g_strdup(CString::new(s).unwrap().as_ptr())
+}
+
+fn error(message: impl Into<String>) -> SdpParseError {
+    SdpParseError { buffer: message.into().into_bytes() }
+}
+
+/// Parses the leading integer of a string, the way C's `atoi` does.
+pub(super) fn atoi(s: &str) -> c_int {
+    let s = s.trim_start();
+    let (negative, digits) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let n = digits.bytes().take_while(u8::is_ascii_digit).fold(0i64, |n, d| (n * 10 + i64::from(d - b'0')).min(1 << 32));
+    (if negative { -n } else { n }).clamp(i64::from(c_int::MIN), i64::from(c_int::MAX)) as c_int
+}
+
+/// Parses a whitespace-separated unsigned number, the way `sscanf` does.
+fn scan<T: std::str::FromStr>(token: Option<&str>) -> Option<T> {
+    let token = token?;
+    let end = token.find(|c: char| !c.is_ascii_digit()).unwrap_or(token.len());
+    token[..end].parse().ok()
+}
+
+fn parse_mtype(s: &str) -> MediaType {
+    match s.to_ascii_lowercase().as_str() {
+        "audio" => MediaType::JANUS_SDP_AUDIO,
+        "video" => MediaType::JANUS_SDP_VIDEO,
+        "application" => MediaType::JANUS_SDP_APPLICATION,
+        _ => MediaType::JANUS_SDP_OTHER,
+    }
+}
+
+fn parse_mdirection(s: &str) -> MediaDirection {
+    match s.to_ascii_lowercase().as_str() {
+        "sendrecv" => MediaDirection::JANUS_SDP_SENDRECV,
+        "sendonly" => MediaDirection::JANUS_SDP_SENDONLY,
+        "recvonly" => MediaDirection::JANUS_SDP_RECVONLY,
+        "inactive" => MediaDirection::JANUS_SDP_INACTIVE,
+        _ => MediaDirection::JANUS_SDP_INVALID,
+    }
+}
+
+fn mdirection_str(direction: MediaDirection) -> Option<&'static str> {
+    match direction {
+        MediaDirection::JANUS_SDP_SENDRECV => Some("sendrecv"),
+        MediaDirection::JANUS_SDP_SENDONLY => Some("sendonly"),
+        MediaDirection::JANUS_SDP_RECVONLY => Some("recvonly"),
+        MediaDirection::JANUS_SDP_INACTIVE => Some("inactive"),
+        _ => None,
+    }
+}
+
+/// Parses the `IN <addrtype> <addr>` of a `c=` line into whether it's IPv4, and the address.
+fn parse_connection(rest: &str) -> Result<(bool, &str), SdpParseError> {
+    let mut tokens = rest.split_whitespace();
+    match (tokens.next(), tokens.next(), tokens.next()) {
+        (Some("IN"), Some(addrtype), Some(addr)) => match addrtype.to_ascii_uppercase().as_str() {
+            "IP4" => Ok((true, addr)),
+            "IP6" => Ok((false, addr)),
+            _ => Err(error(format!("Invalid c= line (unsupported protocol {})", addrtype))),
+        },
+        _ => Err(error("Invalid c= line")),
+    }
+}
+
+unsafe fn parse_attribute(rest: &str) -> Result<*mut RawAttribute, SdpParseError> {
+    let (name, value) = match rest.find(':') {
+        None => (rest, None),
+        Some(i) if i + 1 == rest.len() => return Err(error("Invalid a= line")),
+        Some(i) => (&rest[..i], Some(&rest[i + 1..])),
+    };
+    let attr = alloc::<RawAttribute>();
+    (*attr).name = dup(name);
+    if let Some(value) = value {
+        (*attr).value = dup(value);
+        if value.contains("/sendonly") {
+            (*attr).direction = MediaDirection::JANUS_SDP_SENDONLY;
+        } else if value.contains("/recvonly") {
+            (*attr).direction = MediaDirection::JANUS_SDP_RECVONLY;
+        }
+        if value.contains("/inactive") {
+            (*attr).direction = MediaDirection::JANUS_SDP_INACTIVE;
+        }
+    }
+    Ok(attr)
+}
+
+unsafe fn parse_mline(sdp: &mut RawSdp, rest: &str) -> Result<*mut RawMLine, SdpParseError> {
+    let mut tokens = rest.split_whitespace();
+    let (type_str, port, proto) = match (tokens.next(), scan::<u16>(tokens.next()), tokens.next()) {
+        (Some(type_str), Some(port), Some(proto)) => (type_str, port, proto),
+        _ => return Err(error("Invalid m= line")),
+    };
+    let m = alloc::<RawMLine>();
+    (*m).type_ = parse_mtype(type_str);
+    (*m).type_str = dup(type_str);
+    (*m).port = port as _;
+    (*m).proto = dup(proto);
+    (*m).direction = MediaDirection::JANUS_SDP_DEFAULT;
+    (*m).c_ipv4 = 1;
+    // add it right away, so that it's cleaned up along with the SDP if we fail
+    sdp.m_lines = glib::g_list_append(sdp.m_lines, m as *mut _);
+    for fmt in rest.split(' ').skip(3) {
+        (*m).fmts = glib::g_list_append((*m).fmts, dup(fmt) as *mut _);
+        let pt = atoi(fmt);
+        if pt >= 0 {
+            // payload type data in the list is cast to pointers
+            (*m).ptypes = glib::g_list_append((*m).ptypes, pt as usize as *mut _);
+        }
+    }
+    if (*m).fmts.is_null() || (*m).ptypes.is_null() {
+        return Err(error("Invalid m= line (no payload types)"));
+    }
+    Ok(m)
+}
+
+unsafe fn parse_session_line(sdp: &mut RawSdp, kind: u8, rest: &str) -> Result<(), SdpParseError> {
+    match kind {
+        b'v' => {
+            let version = rest.trim_start();
+            let end = version.find(|c: char| !c.is_ascii_digit() && c != '-').unwrap_or(version.len());
+            sdp.version = version[..end].parse().map_err(|_| error("Invalid v= line"))?;
+        }
+        b'o' => {
+            if !sdp.o_name.is_null() || !sdp.o_addr.is_null() {
+                return Err(error("Multiple o= lines"));
+            }
+            let mut tokens = rest.split_whitespace();
+            let name = tokens.next();
+            let (sessid, version) = (scan(tokens.next()), scan(tokens.next()));
+            match (name, sessid, version, tokens.next(), tokens.next(), tokens.next()) {
+                (Some(name), Some(sessid), Some(version), Some("IN"), Some(addrtype), Some(addr)) => {
+                    sdp.o_ipv4 = match addrtype.to_ascii_uppercase().as_str() {
+                        "IP4" => 1,
+                        "IP6" => 0,
+                        _ => return Err(error(format!("Invalid o= line (unsupported protocol {})", addrtype))),
+                    };
+                    sdp.o_sessid = sessid;
+                    sdp.o_version = version;
+                    sdp.o_name = dup(name);
+                    sdp.o_addr = dup(addr);
+                }
+                _ => return Err(error("Invalid o= line")),
+            }
+        }
+        b's' => {
+            if !sdp.s_name.is_null() {
+                return Err(error("Multiple s= lines"));
+            }
+            sdp.s_name = dup(rest);
+        }
+        b't' => {
+            let mut tokens = rest.split_whitespace();
+            match (scan(tokens.next()), scan(tokens.next())) {
+                (Some(start), Some(stop)) => {
+                    sdp.t_start = start;
+                    sdp.t_stop = stop;
+                }
+                _ => return Err(error("Invalid t= line")),
+            }
+        }
+        b'c' => {
+            if !sdp.c_addr.is_null() {
+                return Err(error("Multiple global c= lines"));
+            }
+            let (ipv4, addr) = parse_connection(rest)?;
+            sdp.c_ipv4 = ipv4 as _;
+            sdp.c_addr = dup(addr);
+        }
+        b'a' => {
+            let attr = parse_attribute(rest)?;
+            sdp.attributes = glib::g_list_append(sdp.attributes, attr as *mut _);
+        }
+        _ => {} // Janus ignores everything else
+    }
+    Ok(())
+}
+
+unsafe fn parse_media_line(m: &mut RawMLine, kind: u8, rest: &str) -> Result<(), SdpParseError> {
+    match kind {
+        b'c' => {
+            if !m.c_addr.is_null() {
+                return Err(error("Multiple m-line c= lines"));
+            }
+            let (ipv4, addr) = parse_connection(rest)?;
+            m.c_ipv4 = ipv4 as _;
+            m.c_addr = dup(addr);
+        }
+        b'b' if m.b_name.is_null() => match rest.find(':') {
+            Some(i) if i + 1 < rest.len() => {
+                // Janus only keeps track of the bandwidth modifiers which WebRTC uses
+                let name = &rest[..i];
+                if name == "AS" || name == "TIAS" {
+                    m.b_name = dup(name);
+                    m.b_value = atoi(&rest[i + 1..]);
+                }
+            }
+            _ => return Err(error("Invalid b= line")),
+        },
+        b'a' => {
+            let direction = if rest.contains(':') { MediaDirection::JANUS_SDP_INVALID } else { parse_mdirection(rest) };
+            if direction != MediaDirection::JANUS_SDP_INVALID {
+                m.direction = direction;
+            } else {
+                let attr = parse_attribute(rest)?;
+                m.attributes = glib::g_list_append(m.attributes, attr as *mut _);
+            }
+        }
+        _ => {} // Janus ignores everything else
+    }
+    Ok(())
+}
+
+unsafe fn parse_lines(sdp: &mut RawSdp, text: &str) -> Result<(), SdpParseError> {
+    let mut mline: Option<*mut RawMLine> = None;
+    for line in text.split('\n') {
+        // like Janus, ignore anything after a carriage return
+        let line = line.split('\r').next().unwrap_or("");
+        if line.is_empty() {
+            continue;
+        }
+        if line.len() < 3 {
+            return Err(error(format!("Invalid line ({} bytes)", line.len())));
+        }
+        if line.as_bytes()[1] != b'=' {
+            return Err(error("Invalid line (no '=')"));
+        }
+        let (kind, rest) = (line.as_bytes()[0], &line[2..]);
+        if kind == b'm' {
+            mline = Some(parse_mline(sdp, rest)?);
+            continue;
+        }
+        match mline {
+            None => parse_session_line(sdp, kind, rest)?,
+            Some(m) => parse_media_line(&mut *m, kind, rest)?,
+        }
+    }
+    if sdp.o_name.is_null() || sdp.o_addr.is_null() || sdp.s_name.is_null() || sdp.m_lines.is_null() {
+        return Err(error("Missing mandatory lines (o=, s= or m=)"));
+    }
+    Ok(())
+}
+
+pub(super) unsafe fn parse(offer: &CStr) -> Result<*mut RawSdp, SdpParseError> {
+    let text = offer.to_str().map_err(|_| error("Invalid SDP (not UTF-8)"))?;
+    if !text.starts_with("v=") {
+        return Err(error("Invalid SDP (doesn't start with v=)"));
+    }
+    let sdp = alloc::<RawSdp>();
+    (*sdp).o_ipv4 = 1;
+    (*sdp).c_ipv4 = 1;
+    match parse_lines(&mut *sdp, text) {
+        Ok(()) => Ok(sdp),
+        Err(e) => {
+            destroy(sdp);
+            Err(e)
+        }
+    }
+}
+
+/// Gets a string the way printf would print it, replacing anything which isn't UTF-8.
+unsafe fn printed<'a>(s: *const c_char) -> Cow<'a, str> {
+    s.as_ref().map_or(Cow::Borrowed("(null)"), |s| CStr::from_ptr(s).to_string_lossy())
+}
+
+fn ip(ipv4: glib::gboolean) -> &'static str {
+    if ipv4 != 0 { "IP4" } else { "IP6" }
+}
+
+unsafe fn write_attribute(out: &mut String, attr: *const RawAttribute) {
+    let attr = &*attr;
+    match attr.value.as_ref() {
+        Some(_) => write!(out, "a={}:{}\r\n", printed(attr.name), printed(attr.value)),
+        None => write!(out, "a={}\r\n", printed(attr.name)),
+    }.unwrap();
+}
+
+unsafe fn write_string(sdp: &RawSdp) -> String {
+    let mut out = String::new();
+    write!(out, "v={}\r\n", sdp.version).unwrap();
+    write!(out, "o={} {} {} IN {} {}\r\n", printed(sdp.o_name), sdp.o_sessid, sdp.o_version, ip(sdp.o_ipv4), printed(sdp.o_addr)).unwrap();
+    write!(out, "s={}\r\n", printed(sdp.s_name)).unwrap();
+    write!(out, "t={} {}\r\n", sdp.t_start, sdp.t_stop).unwrap();
+    if !sdp.c_addr.is_null() {
+        write!(out, "c=IN {} {}\r\n", ip(sdp.c_ipv4), printed(sdp.c_addr)).unwrap();
+    }
+    for attr in GListData::new(sdp.attributes) {
+        write_attribute(&mut out, attr as *const RawAttribute);
+    }
+    for m in GListData::new(sdp.m_lines) {
+        let m = &*(m as *const RawMLine);
+        let port = m.port as u16;
+        write!(out, "m={} {} {}", printed(m.type_str), port, printed(m.proto)).unwrap();
+        if port == 0 && m.type_ != MediaType::JANUS_SDP_APPLICATION {
+            // rejected media don't list their payload types
+            out.push_str(" 0");
+        } else if printed(m.proto).contains("RTP") {
+            for pt in GListData::new(m.ptypes) {
+                write!(out, " {}", pt as usize as c_int).unwrap();
+            }
+        } else {
+            for fmt in GListData::new(m.fmts) {
+                write!(out, " {}", printed(fmt as *const c_char)).unwrap();
+            }
+        }
+        out.push_str("\r\n");
+        if !m.c_addr.is_null() {
+            write!(out, "c=IN {} {}\r\n", ip(m.c_ipv4), printed(m.c_addr)).unwrap();
+        }
+        if port > 0 && !m.b_name.is_null() {
+            write!(out, "b={}:{}\r\n", printed(m.b_name), m.b_value as u32).unwrap();
+        }
+        if let Some(direction) = mdirection_str(m.direction) {
+            write!(out, "a={}\r\n", direction).unwrap();
+        }
+        for attr in GListData::new(m.attributes) {
+            // rejected media only keep their mid
+            if port == 0 && !printed((*(attr as *const RawAttribute)).name).eq_ignore_ascii_case("mid") {
+                continue;
+            }
+            write_attribute(&mut out, attr as *const RawAttribute);
+        }
+    }
+    out
+}
+
+pub(super) unsafe fn write(sdp: *mut RawSdp) -> GLibString {
+    let text = CString::new(write_string(&*sdp)).expect("SDP contains a nul byte :(");
+    GLibString::from_chars(glib::g_strdup(text.as_ptr())).expect("Mysterious error writing SDP to string :(")
+}
+
+unsafe fn destroy_attributes(list: *mut glib::GList) {
+    for attr in GListData::new(list) {
+        destroy_attribute(attr as *mut RawAttribute);
+    }
+    glib::g_list_free(list);
+}
+
+unsafe fn destroy_mline(m: *mut RawMLine) {
+    let ml = &mut *m;
+    for s in &[ml.type_str, ml.proto, ml.c_addr, ml.b_name] {
+        glib::g_free(*s as *mut _);
+    }
+    glib::g_list_free_full(ml.fmts, Some(glib::g_free));
+    glib::g_list_free(ml.ptypes);
+    destroy_attributes(ml.attributes);
+    glib::g_free(m as *mut _);
+}
+
+pub(super) unsafe fn destroy(sdp: *mut RawSdp) {
+    let s = &mut *sdp;
+    for field in &[s.o_name, s.o_addr, s.s_name, s.c_addr] {
+        glib::g_free(*field as *mut _);
+    }
+    destroy_attributes(s.attributes);
+    for m in GListData::new(s.m_lines) {
+        destroy_mline(m as *mut RawMLine);
+    }
+    glib::g_list_free(s.m_lines);
+    glib::g_free(sdp as *mut _);
+}
+
+pub(super) unsafe fn create_attribute(name: &CStr, value: Option<&CStr>) -> *mut RawAttribute {
+    let attr = alloc::<RawAttribute>();
+    (*attr).name = glib::g_strdup(name.as_ptr());
+    (*attr).value = value.map_or(std::ptr::null_mut(), |v| glib::g_strdup(v.as_ptr()));
+    (*attr).direction = MediaDirection::JANUS_SDP_DEFAULT;
+    attr
+}
+
+pub(super) unsafe fn destroy_attribute(attr: *mut RawAttribute) {
+    glib::g_free((*attr).name as *mut _);
+    glib::g_free((*attr).value as *mut _);
+    glib::g_free(attr as *mut _);
+}
+
+/// The rtpmap encodings which Janus recognizes for each codec name, and whether the codec is video.
+fn codec_format(codec: &str) -> Option<(&'static str, bool)> {
+    Some(match codec.to_ascii_lowercase().as_str() {
+        "opus" => ("opus/48000/2", false),
+        "pcmu" => ("pcmu/8000", false),
+        "pcma" => ("pcma/8000", false),
+        "g722" => ("g722/8000", false),
+        "isac16" => ("isac/16000", false),
+        "isac32" => ("isac/32000", false),
+        "dtmf" => ("telephone-event/8000", false),
+        "vp8" => ("vp8/90000", true),
+        "vp9" => ("vp9/90000", true),
+        "h264" => ("h264/90000", true),
+        "av1" => ("av1/90000", true),
+        "h265" => ("h265/90000", true),
+        _ => return None,
+    })
+}
+
+pub(super) unsafe fn get_codec_pt(sdp: *mut RawSdp, codec: &CStr, profile: Option<&CStr>) -> c_int {
+    let codec = codec.to_str().unwrap_or("").to_ascii_lowercase();
+    let profile = profile.map(|p| p.to_str().unwrap_or(""));
+    let (format, video) = match codec_format(&codec) {
+        Some(f) => f,
+        None => return -1,
+    };
+    // like Janus, match the encoding in either all-lowercase or all-uppercase
+    let format_upper = format.to_ascii_uppercase();
+    let (vp9, h264) = (codec == "vp9", codec == "h264");
+    let wanted = if video { MediaType::JANUS_SDP_VIDEO } else { MediaType::JANUS_SDP_AUDIO };
+    for m in GListData::new((*sdp).m_lines) {
+        let m = &*(m as *const RawMLine);
+        if m.type_ != wanted {
+            continue;
+        }
+        // payload types with the right encoding, which we still need to check the profile of
+        let mut candidates = Vec::new();
+        for attr in GListData::new(m.attributes) {
+            let attr = &*(attr as *const RawAttribute);
+            let (name, value) = match (attr.name.as_ref(), attr.value.as_ref()) {
+                (Some(_), Some(_)) => (printed(attr.name), printed(attr.value)),
+                _ => continue,
+            };
+            if let (Some(profile), true) = (profile, name.eq_ignore_ascii_case("fmtp")) {
+                let pt = atoi(&value);
+                if !candidates.contains(&pt) {
+                    continue;
+                }
+                if vp9 {
+                    if value.contains(&format!("profile-id={}", profile)) {
+                        return pt;
+                    }
+                } else if h264 && !value.contains("packetization-mode=0") {
+                    // Janus only supports packetization-mode=1, no matter the profile
+                    if value.contains(&format!("profile-level-id={}", profile.to_ascii_lowercase()))
+                        || value.contains(&format!("profile-level-id={}", profile.to_ascii_uppercase()))
+                    {
+                        return pt;
+                    }
+                }
+            } else if name.eq_ignore_ascii_case("rtpmap") {
+                let pt = atoi(&value);
+                if pt >= 0 && (value.contains(format) || value.contains(&format_upper)) {
+                    if profile.is_some() && (vp9 || h264) {
+                        candidates.push(pt);
+                    } else {
+                        return pt;
+                    }
+                }
+            }
+        }
+    }
+    -1
+}
+
+pub(super) unsafe fn get_codec_name(sdp: *mut RawSdp, pt: c_int) -> Option<&'static str> {
+    match pt {
+        _ if pt < 0 => return None,
+        0 => return Some("pcmu"),
+        8 => return Some("pcma"),
+        9 => return Some("g722"),
+        _ => {}
+    }
+    // like Janus, go by the first rtpmap for the payload type in any media, matching in lowercase or uppercase
+    const NAMES: [(&str, &str); 12] = [
+        ("vp8", "vp8"),
+        ("vp9", "vp9"),
+        ("h264", "h264"),
+        ("av1", "av1"),
+        ("h265", "h265"),
+        ("opus", "opus"),
+        ("pcmu", "pcmu"),
+        ("pcma", "pcma"),
+        ("g722", "g722"),
+        ("isac/16", "isac16"),
+        ("isac/32", "isac32"),
+        ("telephone-event", "dtmf"),
+    ];
+    for m in GListData::new((*sdp).m_lines) {
+        let m = &*(m as *const RawMLine);
+        for attr in GListData::new(m.attributes) {
+            let attr = &*(attr as *const RawAttribute);
+            let (name, value) = match (attr.name.as_ref(), attr.value.as_ref()) {
+                (Some(_), Some(_)) => (printed(attr.name), printed(attr.value)),
+                _ => continue,
+            };
+            if name.eq_ignore_ascii_case("rtpmap") && atoi(&value) == pt {
+                return NAMES
+                    .iter()
+                    .find(|(encoding, _)| value.contains(encoding) || value.contains(&encoding.to_ascii_uppercase()))
+                    .map(|&(_, codec)| codec);
+            }
+        }
+    }
+    None
+}
+
+/// Calls a libjanus function with a copy of the given SDP made by libjanus, since SDPs made by this backend aren't
+/// reference counted the way libjanus expects.
+pub(super) unsafe fn with_janus_sdp<R>(sdp: &Sdp, f: impl FnOnce(*mut RawSdp) -> R) -> R {
+    let text = sdp.to_glibstring();
+    let mut error = [0 as c_char; 512];
+    let copy = ffi::sdp::janus_sdp_parse(text.as_ptr(), error.as_mut_ptr(), error.len());
+    let result = f(copy);
+    if !copy.is_null() {
+        ffi::sdp::janus_sdp_destroy(copy);
+    }
+    result
+}
+
+/// Takes ownership of an SDP which libjanus returned, by copying it into an SDP made by this backend.
+pub(super) unsafe fn adopt_janus_sdp(ptr: *mut RawSdp) -> Option<Sdp> {
+    if ptr.is_null() {
+        return None;
+    }
+    let text = GLibString::from_chars(ffi::sdp::janus_sdp_write(ptr));
+    ffi::sdp::janus_sdp_destroy(ptr);
+    parse(&text?).ok().and_then(|ptr| Sdp::new(ptr))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    static CORPUS: [(&str, &str, &str); 3] = [
+        ("chrome", include_str!("testdata/chrome.sdp"), include_str!("testdata/chrome.janus.sdp")),
+        ("firefox", include_str!("testdata/firefox.sdp"), include_str!("testdata/firefox.janus.sdp")),
+        ("simulcast", include_str!("testdata/simulcast.sdp"), include_str!("testdata/simulcast.janus.sdp")),
+    ];
+
+    fn parse_str(sdp: &str) -> Result<Sdp, SdpParseError> {
+        Sdp::parse(&CString::new(sdp).unwrap())
+    }
+
+    #[test]
+    fn round_trip_browser_offers() {
+        for (name, offer, written) in &CORPUS {
+            let sdp = parse_str(offer).unwrap();
+            assert_eq!(sdp.to_glibstring().to_str().unwrap(), *written, "{} offer", name);
+            let sdp = parse_str(written).unwrap();
+            assert_eq!(sdp.to_glibstring().to_str().unwrap(), *written, "{} rewritten", name);
+        }
+    }
+
+    #[test]
+    fn parse_structure() {
+        let sdp = parse_str(CORPUS[1].1).unwrap();
+        assert_eq!(sdp.o_sessid, 7064016335724443539);
+        let mlines = sdp.mlines().collect::<Vec<_>>();
+        assert_eq!(mlines.iter().map(|m| m.media_type()).collect::<Vec<_>>(), vec![
+            MediaType::JANUS_SDP_AUDIO,
+            MediaType::JANUS_SDP_VIDEO,
+            MediaType::JANUS_SDP_VIDEO,
+        ]);
+        assert_eq!(mlines[0].payload_types().collect::<Vec<_>>(), vec![109, 9, 0, 8, 101]);
+        assert_eq!(mlines[1].bandwidth(), Some(("TIAS", 2500000)));
+        assert_eq!(mlines[2].port(), 0);
+        assert_eq!(mlines[2].direction(), MediaDirection::JANUS_SDP_INACTIVE);
+        let extmap = mlines[0].attributes().find(|a| matches!(a.value(), Some(v) if v.starts_with("2/"))).unwrap();
+        assert_eq!(extmap.direction(), MediaDirection::JANUS_SDP_RECVONLY);
+        assert_eq!(sdp.attributes().map(|a| a.name()).collect::<Vec<_>>(), vec!["fingerprint", "group", "ice-options", "msid-semantic"]);
+    }
+
+    #[test]
+    fn reject_invalid_sdps() {
+        let cases = [
+            ("o=- 1 1 IN IP4 0.0.0.0\r\n", "Invalid SDP (doesn't start with v=)"),
+            ("v=0\r\no=- 1 1 IN IP4 0.0.0.0\r\ns=-\r\nt=0 0\r\n", "Missing mandatory lines (o=, s= or m=)"),
+            ("v=0\r\nx\r\n", "Invalid line (1 bytes)"),
+            ("v=0\r\no=- 1 1 IN IPX 0.0.0.0\r\n", "Invalid o= line (unsupported protocol IPX)"),
+            ("v=0\r\no=- 1 1 IN IP4 0.0.0.0\r\ns=-\r\nm=audio 9 RTP/AVP 0\r\na=fmtp:\r\n", "Invalid a= line"),
+        ];
+        for (sdp, message) in &cases {
+            assert_eq!(parse_str(sdp).unwrap_err().to_string(), *message);
+        }
+        let latin1 = CString::new(&b"v=0\r\ns=caf\xe9\r\n"[..]).unwrap();
+        assert_eq!(Sdp::parse(&latin1).unwrap_err().to_string(), "Invalid SDP (not UTF-8)");
+    }
+
+    #[test]
+    fn find_codec_payload_types() {
+        let chrome = parse_str(CORPUS[0].1).unwrap();
+        let pt = |codec: &str, profile: Option<&str>| match profile {
+            Some(p) => chrome.get_payload_type_full(&CString::new(codec).unwrap(), &CString::new(p).unwrap()),
+            None => chrome.get_payload_type(&CString::new(codec).unwrap()),
+        };
+        assert_eq!(pt("opus", None), Some(111));
+        assert_eq!(pt("pcmu", None), Some(0));
+        assert_eq!(pt("vp9", None), Some(98));
+        assert_eq!(pt("vp9", Some("2")), Some(100));
+        assert_eq!(pt("h264", Some("42e01f")), Some(45)); // 104 has packetization-mode=0
+        assert_eq!(pt("H264", Some("42E01F")), Some(45));
+        assert_eq!(pt("av1", None), Some(35));
+        assert_eq!(pt("h265", None), None);
+        assert_eq!(pt("speex", None), None);
+    }
+
+    #[test]
+    fn edit_through_views() {
+        let mut sdp = parse_str(CORPUS[2].1).unwrap();
+        {
+            let video = sdp.mlines_mut().next().unwrap();
+            video.set_port(0);
+            video.set_port(9);
+            video.set_direction(MediaDirection::JANUS_SDP_RECVONLY);
+            video.set_bandwidth(Some(("AS", 512)));
+            video.set_payload_types(&[96]);
+            video.retain_attributes(|a| {
+                !matches!(a.value(), Some(v) if v.starts_with("97 ")) && a.name() != "rid" && a.name() != "simulcast"
+            });
+            video.add_attribute("x-note", Some("100% fake"));
+            for attr in video.attributes_mut().filter(|a| a.name() == "mid") {
+                attr.set_value(Some("video"));
+            }
+        }
+        sdp.add_session_attribute("ice-lite", None);
+        let written = sdp.to_glibstring().to_str().unwrap().to_owned();
+        assert!(written.contains("a=ice-lite\r\nm=video 9 UDP/TLS/RTP/SAVPF 96\r\nc=IN IP4 0.0.0.0\r\nb=AS:512\r\na=recvonly\r\n"));
+        assert!(written.contains("a=mid:video\r\n"));
+        assert!(written.ends_with("a=rtcp-fb:96 nack pli\r\na=x-note:100% fake\r\n"));
+        assert!(!written.contains("a=rid") && !written.contains("rtx"));
+    }
+
+    #[test]
+    fn tolerate_invalid_utf8() {
+        let chrome = parse_str(CORPUS[0].1).unwrap();
+        let mut sdp = parse_str(CORPUS[0].1).unwrap();
+        unsafe {
+            // e.g. an SDP which came from elsewhere as a raw pointer, without being checked by parsing
+            let audio = sdp.mlines_mut().next().unwrap().as_raw_mut();
+            let attr = GListData::new(audio.attributes)
+                .map(|a| a as *mut RawAttribute)
+                .find(|&a| CStr::from_ptr((*a).name).to_bytes() == b"extmap")
+                .unwrap();
+            glib::g_free((*attr).name as *mut _);
+            (*attr).name = glib::g_strdup(b"ext\xffmap\0".as_ptr() as *const c_char);
+            let fmt = audio.fmts;
+            glib::g_free((*fmt).data);
+            (*fmt).data = glib::g_strdup(b"\xff\0".as_ptr() as *const c_char) as *mut _;
+        }
+        let audio = sdp.mlines().next().unwrap();
+        assert_eq!(audio.attributes().filter(|a| a.name().is_empty()).count(), 1);
+        assert_eq!(audio.formats().count(), 7);
+        assert_eq!(sdp.extmaps().len(), chrome.extmaps().len() - 1);
+        assert!(sdp.diff(&chrome).mlines.is_empty());
+    }
+}
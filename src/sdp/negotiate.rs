@@ -0,0 +1,222 @@
+/// Picking codecs for an answer from an ordered list of preferences, according to what an offer supports.
+use std::ffi::{CStr, CString};
+use super::{AnswerBuilder, AudioCodec, MediaType, Sdp, VideoCodec};
+
+/// A codec which Janus knows how to negotiate.
+pub trait Codec: Copy + 'static {
+    /// The kind of media this codec is for.
+    const MEDIA_TYPE: MediaType;
+
+    /// Every codec of this kind.
+    const ALL: &'static [Self];
+
+    /// The name Janus uses for this codec.
+    fn to_cstr(self) -> &'static CStr;
+}
+
+impl Codec for AudioCodec {
+    const MEDIA_TYPE: MediaType = MediaType::JANUS_SDP_AUDIO;
+    const ALL: &'static [Self] = &[
+        AudioCodec::Opus,
+        AudioCodec::Pcmu,
+        AudioCodec::Pcma,
+        AudioCodec::G722,
+        AudioCodec::Isac16,
+        AudioCodec::Isac32,
+    ];
+
+    fn to_cstr(self) -> &'static CStr {
+        AudioCodec::to_cstr(self)
+    }
+}
+
+impl Codec for VideoCodec {
+    const MEDIA_TYPE: MediaType = MediaType::JANUS_SDP_VIDEO;
+    const ALL: &'static [Self] = &[VideoCodec::Vp8, VideoCodec::Vp9, VideoCodec::H264, VideoCodec::Av1, VideoCodec::H265];
+
+    fn to_cstr(self) -> &'static CStr {
+        VideoCodec::to_cstr(self)
+    }
+}
+
+/// One entry in a list of codec preferences: a codec, optionally restricted to a profile (the VP9 `profile-id` or the
+/// H.264 `profile-level-id`) and to offers whose fmtp for the codec has certain parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodecPreference<C> {
+    pub codec: C,
+    pub profile: Option<String>,
+    pub fmtp: Vec<(String, String)>,
+}
+
+impl<C: Codec> CodecPreference<C> {
+    /// Accepts the codec with any profile and parameters.
+    pub fn new(codec: C) -> Self {
+        Self { codec, profile: None, fmtp: Vec::new() }
+    }
+
+    /// Only accepts the codec with the given profile, e.g. "2" for VP9 or "42e01f" for H.264.
+    pub fn profile(mut self, profile: &str) -> Self {
+        self.profile = Some(profile.to_owned());
+        self
+    }
+
+    /// Only accepts the codec if the offer's fmtp for it has the given parameter, e.g. `useinbandfec=1` for Opus.
+    pub fn require_fmtp(mut self, name: &str, value: &str) -> Self {
+        self.fmtp.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Finds the payload type of this codec in the offer, if the offer supports it as required.
+    fn find(&self, offer: &Sdp) -> Option<i32> {
+        let pt = match self.profile {
+            Some(ref profile) => offer.get_payload_type_full(self.codec.to_cstr(), &CString::new(profile.as_str()).ok()?)?,
+            None => offer.get_payload_type(self.codec.to_cstr())?,
+        };
+        if self.fmtp.is_empty() {
+            return Some(pt);
+        }
+        let params = offer
+            .mlines()
+            .filter(|m| m.media_type() == C::MEDIA_TYPE)
+            .find_map(|m| m.fmtp(pt))?;
+        let satisfied = self.fmtp.iter().all(|(name, value)| params.get(name) == Some(value.as_str()));
+        if satisfied { Some(pt) } else { None }
+    }
+}
+
+/// A codec which was picked from an offer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NegotiatedCodec<C> {
+    pub codec: C,
+    /// The payload type the offer uses for the codec.
+    pub payload_type: i32,
+    /// The profile required by the preference which matched, if any.
+    pub profile: Option<String>,
+}
+
+/// The codecs picked from an offer. A codec is None if the policy has preferences for its kind of media, but the
+/// offer supports none of them.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Negotiation {
+    pub audio: Option<NegotiatedCodec<AudioCodec>>,
+    pub video: Option<NegotiatedCodec<VideoCodec>>,
+    audio_rejected: bool,
+    video_rejected: bool,
+}
+
+impl Negotiation {
+    /// Configures an answer to use the picked codecs. Media for which no preferred codec was offered is rejected;
+    /// media for which the policy has no preferences is left up to Janus.
+    pub fn apply<'a>(&self, mut answer: AnswerBuilder<'a>) -> AnswerBuilder<'a> {
+        if let Some(ref audio) = self.audio {
+            answer = answer.audio_codec(audio.codec);
+        } else if self.audio_rejected {
+            answer = answer.audio(false);
+        }
+        if let Some(ref video) = self.video {
+            answer = answer.video_codec(video.codec);
+            answer = match (video.codec, video.profile.as_deref()) {
+                (VideoCodec::Vp9, Some(profile)) => answer.vp9_profile(profile),
+                (VideoCodec::H264, Some(profile)) => answer.h264_profile(profile),
+                _ => answer,
+            };
+        } else if self.video_rejected {
+            answer = answer.video(false);
+        }
+        answer
+    }
+}
+
+/// An ordered list of preferred audio and video codecs, e.g. "AV1, then VP9 profile 2, then VP8, then H.264
+/// constrained baseline".
+///
+/// ```ignore
+/// let policy = CodecPolicy::new()
+///     .prefer_audio(CodecPreference::new(AudioCodec::Opus))
+///     .prefer_video(CodecPreference::new(VideoCodec::Av1))
+///     .prefer_video(CodecPreference::new(VideoCodec::Vp9).profile("2"))
+///     .prefer_video(CodecPreference::new(VideoCodec::Vp8))
+///     .prefer_video(CodecPreference::new(VideoCodec::H264).profile("42e01f"));
+/// let answer = policy.negotiate(&offer).apply(AnswerBuilder::new(&offer)).build()?;
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CodecPolicy {
+    audio: Vec<CodecPreference<AudioCodec>>,
+    video: Vec<CodecPreference<VideoCodec>>,
+}
+
+impl CodecPolicy {
+    /// Creates a policy with no preferences.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an audio codec, less preferred than any added before it.
+    pub fn prefer_audio(mut self, preference: CodecPreference<AudioCodec>) -> Self {
+        self.audio.push(preference);
+        self
+    }
+
+    /// Adds a video codec, less preferred than any added before it.
+    pub fn prefer_video(mut self, preference: CodecPreference<VideoCodec>) -> Self {
+        self.video.push(preference);
+        self
+    }
+
+    /// Picks the most preferred audio and video codecs which the offer supports.
+    pub fn negotiate(&self, offer: &Sdp) -> Negotiation {
+        Negotiation {
+            audio: pick(&self.audio, offer),
+            video: pick(&self.video, offer),
+            audio_rejected: !self.audio.is_empty(),
+            video_rejected: !self.video.is_empty(),
+        }
+    }
+}
+
+fn pick<C: Codec>(preferences: &[CodecPreference<C>], offer: &Sdp) -> Option<NegotiatedCodec<C>> {
+    preferences.iter().find_map(|p| {
+        p.find(offer).map(|payload_type| NegotiatedCodec { codec: p.codec, payload_type, profile: p.profile.clone() })
+    })
+}
+
+#[cfg(all(test, feature = "rust-sdp"))]
+mod tests {
+
+    use super::*;
+
+    fn chrome_offer() -> Sdp {
+        Sdp::parse(&CString::new(include_str!("testdata/chrome.sdp")).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn pick_preferred_codecs() {
+        let offer = chrome_offer();
+        let policy = CodecPolicy::new()
+            .prefer_video(CodecPreference::new(VideoCodec::H265))
+            .prefer_video(CodecPreference::new(VideoCodec::Vp9).profile("2"))
+            .prefer_video(CodecPreference::new(VideoCodec::Vp8));
+        let negotiation = policy.negotiate(&offer);
+        assert_eq!(negotiation.video, Some(NegotiatedCodec { codec: VideoCodec::Vp9, payload_type: 100, profile: Some("2".into()) }));
+        assert_eq!(negotiation.audio, None);
+
+        let policy = CodecPolicy::new().prefer_video(CodecPreference::new(VideoCodec::H264).profile("42e01f"));
+        assert_eq!(policy.negotiate(&offer).video.unwrap().payload_type, 45);
+    }
+
+    #[test]
+    fn check_fmtp_constraints() {
+        let offer = chrome_offer();
+        let policy = CodecPolicy::new()
+            .prefer_audio(CodecPreference::new(AudioCodec::Opus).require_fmtp("stereo", "1"))
+            .prefer_audio(CodecPreference::new(AudioCodec::Opus).require_fmtp("useinbandfec", "1"))
+            .prefer_audio(CodecPreference::new(AudioCodec::Pcmu));
+        let audio = policy.negotiate(&offer).audio.unwrap();
+        assert_eq!((audio.codec, audio.payload_type), (AudioCodec::Opus, 111));
+
+        let policy = CodecPolicy::new().prefer_audio(CodecPreference::new(AudioCodec::Opus).require_fmtp("stereo", "1"));
+        let negotiation = policy.negotiate(&offer);
+        assert_eq!(negotiation.audio, None);
+        assert!(negotiation.audio_rejected && !negotiation.video_rejected);
+    }
+}
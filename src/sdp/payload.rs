@@ -0,0 +1,313 @@
+/// Rewriting and removing the RTP payload types in an SDP, token by token.
+use std::collections::HashMap;
+use super::{backend, Codec, FmtpParameters, MLine, Rid, RtxParameters, Sdp, MEDIA_PAYLOAD_ATTRIBUTES};
+
+fn map_pt(pt: &str, mapping: &HashMap<i32, i32>) -> String {
+    match pt.parse::<i32>().ok().and_then(|p| mapping.get(&p)) {
+        Some(to) => to.to_string(),
+        None => pt.to_owned(),
+    }
+}
+
+/// Rewrites the payload types referred to by fmtp parameters: the `apt=` of RTX, or the list of redundant payload
+/// types of RED (RFC 2198), e.g. `111/111`.
+fn rewrite_fmtp_params(params: &str, mapping: &HashMap<i32, i32>) -> String {
+    if !params.is_empty() && params.split('/').all(|pt| pt.parse::<i32>().is_ok()) {
+        return params.split('/').map(|pt| map_pt(pt, mapping)).collect::<Vec<_>>().join("/");
+    }
+    let mut parsed = params.parse::<FmtpParameters>().unwrap();
+    match parsed.get("apt").map(|apt| map_pt(apt, mapping)) {
+        Some(apt) if Some(apt.as_str()) != parsed.get("apt") => {
+            parsed.set("apt", apt);
+            parsed.to_string()
+        }
+        _ => params.to_owned(),
+    }
+}
+
+/// Computes the value of an attribute after rewriting its payload types, or None if it doesn't change.
+fn rewrite_attribute(name: &str, value: &str, mapping: &HashMap<i32, i32>) -> Option<String> {
+    let rewritten = if MEDIA_PAYLOAD_ATTRIBUTES.contains(&name) {
+        // each of these look like "$pt $stuff", where $stuff specifies payload-type-specific options
+        match value.split_once(' ') {
+            Some((pt, rest)) if name == "fmtp" => format!("{} {}", map_pt(pt, mapping), rewrite_fmtp_params(rest, mapping)),
+            Some((pt, rest)) => format!("{} {}", map_pt(pt, mapping), rest),
+            None => map_pt(value, mapping),
+        }
+    } else if name == "rid" {
+        let mut rid = Rid::parse(value)?;
+        for pt in rid.payload_types.iter_mut() {
+            *pt = *mapping.get(pt).unwrap_or(pt);
+        }
+        rid.to_string()
+    } else {
+        return None;
+    };
+    if rewritten != value { Some(rewritten) } else { None }
+}
+
+impl MLine {
+    /// Rewrites every reference to the payload types in the keys of the mapping to the corresponding values: in the
+    /// `m=` line, in `a=rtpmap`, `a=fmtp` and `a=rtcp-fb` attributes, in RTX `apt=` and RED parameters, and in `a=rid`
+    /// restrictions. Every reference is rewritten at once, so mappings can swap payload types around.
+    pub fn rewrite_payload_types(&mut self, mapping: &HashMap<i32, i32>) {
+        if self.payload_types().any(|pt| mapping.contains_key(&pt)) {
+            let formats = self.formats().map(|f| map_pt(f, mapping)).collect::<Vec<_>>();
+            self.set_formats(&formats.iter().map(String::as_str).collect::<Vec<_>>());
+        }
+        for attr in self.attributes_mut() {
+            if let Some(value) = attr.value().and_then(|v| rewrite_attribute(attr.name(), v, mapping)) {
+                attr.set_value(Some(&value));
+            }
+        }
+    }
+
+    /// Removes the payload type from the `m=` line, along with the `a=rtpmap`, `a=fmtp` and `a=rtcp-fb` attributes
+    /// for it and its `pt=` references in `a=rid` restrictions. RIDs restricted to only this payload type are removed,
+    /// along with their simulcast alternatives, as RFC 8851 requires.
+    pub fn remove_payload_type(&mut self, pt: i32) {
+        let is_pt = |token: &str| token.parse::<i32>() == Ok(pt);
+        if self.formats().any(is_pt) {
+            let formats = self.formats().filter(|f| !is_pt(f)).map(str::to_owned).collect::<Vec<_>>();
+            self.set_formats(&formats.iter().map(String::as_str).collect::<Vec<_>>());
+        }
+        self.retain_attributes(|a| {
+            let pt_token = a.value().and_then(|v| v.split(' ').next());
+            !MEDIA_PAYLOAD_ATTRIBUTES.contains(&a.name()) || !matches!(pt_token, Some(token) if is_pt(token))
+        });
+        let mut rejected = Vec::new();
+        for attr in self.attributes_mut().filter(|a| a.name() == "rid") {
+            let mut rid = match attr.value().and_then(Rid::parse) {
+                Some(rid) if rid.payload_types.contains(&pt) => rid,
+                _ => continue,
+            };
+            rid.payload_types.retain(|&p| p != pt);
+            if rid.payload_types.is_empty() {
+                rejected.push(rid.id);
+            } else {
+                attr.set_value(Some(&rid.to_string()));
+            }
+        }
+        self.remove_rids(&rejected);
+    }
+
+    /// The name Janus uses for the codec with the given payload type in this media, e.g. "opus", going by this
+    /// media's own `a=rtpmap` attributes (or the static payload types Janus knows). None if the payload type isn't
+    /// mapped to a codec Janus knows, e.g. if it's for RTX.
+    pub fn codec_name(&self, pt: i32) -> Option<&'static str> {
+        match pt {
+            0 => return Some("pcmu"),
+            8 => return Some("pcma"),
+            9 => return Some("g722"),
+            _ => {}
+        }
+        let rtpmap = self.attributes().filter(|a| a.name() == "rtpmap").filter_map(|a| a.value()).find_map(|v| match v.split_once(' ') {
+            Some((p, encoding)) if p.parse::<i32>() == Ok(pt) => Some(encoding),
+            _ => None,
+        })?;
+        let mut tokens = rtpmap.split('/');
+        let name = tokens.next()?.to_ascii_lowercase();
+        Some(match (name.as_str(), tokens.next()) {
+            ("vp8", _) => "vp8",
+            ("vp9", _) => "vp9",
+            ("h264", _) => "h264",
+            ("av1", _) => "av1",
+            ("h265", _) => "h265",
+            ("opus", _) => "opus",
+            ("pcmu", _) => "pcmu",
+            ("pcma", _) => "pcma",
+            ("g722", _) => "g722",
+            ("isac", Some("16000")) => "isac16",
+            ("isac", Some("32000")) => "isac32",
+            ("telephone-event", _) => "dtmf",
+            _ => return None,
+        })
+    }
+
+    /// Removes every codec of the given kind except the ones to keep from this media, along with the RTX payload
+    /// types for them. See `Sdp::retain_codecs`. If that would leave the media without any payload types, it's
+    /// rejected instead, by setting its port to 0, and its payload types are left alone.
+    pub fn retain_codecs<C: Codec>(&mut self, keep: &[C]) {
+        let named = |codecs: &[C], name: &str| codecs.iter().any(|c| c.to_cstr().to_bytes() == name.as_bytes());
+        let is_removed_codec = |pt: i32| matches!(self.codec_name(pt), Some(name) if named(C::ALL, name) && !named(keep, name));
+        let codecs = self.payload_types().filter(|&pt| is_removed_codec(pt)).collect::<Vec<_>>();
+        let is_rtx = |pt: i32| matches!(self.codec_parameters::<RtxParameters>(pt).apt, Some(apt) if codecs.contains(&apt));
+        let removed = self.payload_types().filter(|&pt| is_rtx(pt)).chain(codecs.iter().cloned()).collect::<Vec<_>>();
+        if removed.is_empty() {
+            return;
+        }
+        if self.payload_types().all(|pt| removed.contains(&pt)) {
+            self.set_port(0);
+            return;
+        }
+        for pt in removed {
+            self.remove_payload_type(pt);
+        }
+    }
+}
+
+impl Sdp {
+    /// Rewrites every reference to the payload types in the keys of the mapping to the corresponding values, in every
+    /// media. See `MLine::rewrite_payload_types`.
+    pub fn rewrite_payload_types(&mut self, mapping: &HashMap<i32, i32>) {
+        for mline in self.mlines_mut() {
+            mline.rewrite_payload_types(mapping);
+        }
+    }
+
+    /// The name Janus uses for the codec with the given payload type, e.g. "opus", or "dtmf" for telephone events.
+    /// None if the payload type isn't mapped to a codec Janus knows, e.g. if it's for RTX.
+    ///
+    /// Like `janus_sdp_get_codec_name`, this goes by the first `a=rtpmap` for the payload type in any media, so it's
+    /// wrong for media which use the payload type for a different codec. Use `MLine::codec_name` to look it up in a
+    /// particular media.
+    pub fn codec_name(&self, pt: i32) -> Option<&'static str> {
+        unsafe { backend::get_codec_name(self.ptr, pt) }
+    }
+
+    /// Removes the payload type from every media, along with the attributes for it. See `MLine::remove_payload_type`.
+    ///
+    /// Unlike `janus_sdp_remove_payload_type`, this leaves alone any attributes whose values merely start with the
+    /// payload type's number, and removes the payload type from the `m=` line formats too.
+    pub fn remove_payload_type(&mut self, pt: i32) {
+        for mline in self.mlines_mut() {
+            mline.remove_payload_type(pt);
+        }
+    }
+
+    /// Removes every codec of the given kind except the ones to keep from the media of that kind, e.g.
+    /// `sdp.retain_codecs(&[VideoCodec::Vp8, VideoCodec::H264])` strips VP9, AV1 and H.265. The RTX payload types for
+    /// the removed codecs are removed too. Payload types for other things, like RED or FEC, are left alone. Each
+    /// media's codecs are looked up in its own `a=rtpmap` attributes, and media left with no codecs are rejected. See
+    /// `MLine::retain_codecs`.
+    pub fn retain_codecs<C: Codec>(&mut self, keep: &[C]) {
+        for mline in self.mlines_mut().filter(|m| m.media_type() == C::MEDIA_TYPE) {
+            mline.retain_codecs(keep);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "rust-sdp"))]
+mod tests {
+
+    use super::*;
+    use crate::sdp::VideoCodec;
+    use std::ffi::CString;
+
+    fn parse(sdp: &str) -> Sdp {
+        Sdp::parse(&CString::new(sdp).unwrap()).unwrap()
+    }
+
+    fn values<'a>(mline: &'a MLine, name: &'a str) -> Vec<&'a str> {
+        mline.attributes().filter(|a| a.name() == name).filter_map(|a| a.value()).collect()
+    }
+
+    #[test]
+    fn rewrite_exact_tokens() {
+        let mut sdp = parse(include_str!("testdata/chrome.sdp"));
+        sdp.rewrite_payload_type(9, 109);
+        let audio = sdp.mlines().next().unwrap();
+        assert_eq!(audio.payload_types().collect::<Vec<_>>(), vec![111, 63, 109, 0, 8, 13, 110, 126]);
+        assert!(values(audio, "rtpmap").contains(&"109 G722/8000"));
+        let video = sdp.mlines().nth(1).unwrap();
+        assert_eq!(values(video, "rtpmap")[0], "96 VP8/90000");
+
+        sdp.rewrite_payload_types(&[(111, 109), (109, 111)].iter().cloned().collect());
+        let audio = sdp.mlines().next().unwrap();
+        assert_eq!(audio.payload_types().take(3).collect::<Vec<_>>(), vec![109, 63, 111]);
+        assert_eq!(&values(audio, "fmtp")[..], &["109 minptime=10;useinbandfec=1", "63 109/109"]);
+        assert_eq!(values(audio, "rtcp-fb")[0], "109 transport-cc");
+    }
+
+    #[test]
+    fn rewrite_rtx_references() {
+        let mut sdp = parse(include_str!("testdata/chrome.sdp"));
+        sdp.rewrite_payload_types(&[(96, 97), (97, 96)].iter().cloned().collect());
+        let video = sdp.mlines().nth(1).unwrap();
+        assert_eq!(video.payload_types().take(3).collect::<Vec<_>>(), vec![97, 96, 98]);
+        assert_eq!(video.formats().take(2).collect::<Vec<_>>(), vec!["97", "96"]);
+        assert_eq!(&values(video, "rtpmap")[..2], &["97 VP8/90000", "96 rtx/90000"]);
+        assert_eq!(values(video, "fmtp")[0], "96 apt=97");
+
+        let mut sdp = parse(include_str!("testdata/simulcast.sdp"));
+        let rids = |sdp: &Sdp| sdp.mlines().flat_map(|m| m.rids()).map(|r| r.to_string()).collect::<Vec<_>>();
+        let before = rids(&sdp);
+        sdp.rewrite_payload_types(&[(96, 120)].iter().cloned().collect());
+        assert_eq!(rids(&sdp), before);
+        let rid = "h send pt=96;max-fps=30";
+        assert_eq!(rewrite_attribute("rid", rid, &[(96, 120)].iter().cloned().collect()).unwrap(), "h send pt=120;max-fps=30");
+    }
+
+    #[test]
+    fn remove_codecs() {
+        let mut sdp = parse(include_str!("testdata/chrome.sdp"));
+        let names = [111, 63, 9, 0, 8, 126, 96, 97, 100, 35, 45].iter().map(|&pt| sdp.codec_name(pt)).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                Some("opus"), None, Some("g722"), Some("pcmu"), Some("pcma"), Some("dtmf"),
+                Some("vp8"), None, Some("vp9"), Some("av1"), Some("h264"),
+            ]
+        );
+
+        sdp.remove_payload_type(9);
+        let audio = sdp.mlines().next().unwrap();
+        assert_eq!(audio.payload_types().collect::<Vec<_>>(), vec![111, 63, 0, 8, 13, 110, 126]);
+        assert!(values(audio, "rtpmap").iter().all(|v| !v.starts_with("9 ")));
+        assert_eq!(sdp.get_payload_type(VideoCodec::Vp8.to_cstr()), Some(96));
+
+        sdp.retain_codecs(&[VideoCodec::Vp8, VideoCodec::Vp9, VideoCodec::H264]);
+        let video = sdp.mlines().nth(1).unwrap();
+        assert_eq!(video.payload_types().collect::<Vec<_>>(), vec![96, 97, 98, 99, 100, 101, 102, 103, 104, 105, 45, 46]);
+        assert_eq!(video.formats().count(), 12);
+        let references_av1 = |v: &&str| v.starts_with("35 ") || v.starts_with("36 ");
+        assert!(video.attributes().filter_map(|a| a.value()).all(|v| !references_av1(&v)));
+        assert_eq!(sdp.mlines().next().unwrap().payload_types().count(), 7);
+
+        sdp.retain_codecs(&[VideoCodec::H264]);
+        let video = sdp.mlines().nth(1).unwrap();
+        assert_eq!(video.payload_types().collect::<Vec<_>>(), vec![102, 103, 104, 105, 45, 46]);
+    }
+
+    #[test]
+    fn retain_codecs_per_media() {
+        let mut sdp = parse(
+            "v=0\r\no=- 1 2 IN IP4 127.0.0.1\r\ns=-\r\nt=0 0\r\n\
+             m=video 9 UDP/TLS/RTP/SAVPF 96 97 98\r\nc=IN IP4 0.0.0.0\r\n\
+             a=rtpmap:96 VP8/90000\r\na=rtpmap:97 rtx/90000\r\na=fmtp:97 apt=96\r\na=rtpmap:98 VP9/90000\r\n\
+             a=rid:h send pt=96,98\r\na=rid:m send pt=98\r\na=rid:l send\r\na=simulcast:send h;m;~l\r\n\
+             m=video 9 UDP/TLS/RTP/SAVPF 96 97 98\r\nc=IN IP4 0.0.0.0\r\n\
+             a=rtpmap:96 VP9/90000\r\na=rtpmap:97 rtx/90000\r\na=fmtp:97 apt=96\r\na=rtpmap:98 VP8/90000\r\n",
+        );
+        let names = |sdp: &Sdp| sdp.mlines().map(|m| m.codec_name(96)).collect::<Vec<_>>();
+        assert_eq!(names(&sdp), vec![Some("vp8"), Some("vp9")]);
+        assert_eq!(sdp.codec_name(96), Some("vp8"));
+
+        sdp.retain_codecs(&[VideoCodec::Vp8]);
+        let mlines = sdp.mlines().collect::<Vec<_>>();
+        assert_eq!(mlines[0].payload_types().collect::<Vec<_>>(), vec![96, 97]);
+        assert_eq!(mlines[1].payload_types().collect::<Vec<_>>(), vec![98]);
+        assert_eq!(values(mlines[1], "rtpmap"), vec!["98 VP8/90000"]);
+        assert_eq!(values(mlines[0], "rid"), vec!["h send pt=96", "l send"]);
+        assert_eq!(mlines[0].attribute("simulcast").and_then(|a| a.value()), Some("send h;~l"));
+
+        sdp.retain_codecs(&[VideoCodec::H264]);
+        let mlines = sdp.mlines().collect::<Vec<_>>();
+        assert_eq!(mlines.iter().map(|m| m.port()).collect::<Vec<_>>(), vec![0, 0]);
+        assert_eq!(mlines[0].payload_types().collect::<Vec<_>>(), vec![96, 97]);
+        assert_eq!(mlines[1].payload_types().collect::<Vec<_>>(), vec![98]);
+    }
+
+    #[test]
+    fn remove_rid_payload_types() {
+        let mut sdp = parse(&include_str!("testdata/simulcast.sdp").replace("a=rid:h send", "a=rid:h send pt=97"));
+        sdp.remove_payload_type(97);
+        let video = sdp.mlines().next().unwrap();
+        assert_eq!(video.rids().map(|r| r.id).collect::<Vec<_>>(), vec!["m", "l"]);
+        assert_eq!(video.simulcast().unwrap().to_string(), "send m;~l");
+
+        sdp.remove_payload_type(96);
+        let video = sdp.mlines().next().unwrap();
+        assert_eq!(video.rids().count(), 2);
+    }
+}
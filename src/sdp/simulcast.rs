@@ -0,0 +1,382 @@
+/// Simulcast descriptions: `a=rid` (RFC 8851), `a=simulcast` (RFC 8853) and legacy `a=ssrc-group` attributes.
+use std::fmt;
+use super::{FmtpParameters, MLine, Sdp};
+
+/// Whether a stream is sent or received, from the point of view of the SDP's author.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StreamDirection {
+    Send,
+    Recv,
+}
+
+impl StreamDirection {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "send" => Some(StreamDirection::Send),
+            "recv" => Some(StreamDirection::Recv),
+            _ => None,
+        }
+    }
+
+    /// The direction the other side of the session sees.
+    pub fn reverse(self) -> Self {
+        match self {
+            StreamDirection::Send => StreamDirection::Recv,
+            StreamDirection::Recv => StreamDirection::Send,
+        }
+    }
+}
+
+impl fmt::Display for StreamDirection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            StreamDirection::Send => "send",
+            StreamDirection::Recv => "recv",
+        })
+    }
+}
+
+/// A restriction identifier, e.g. `a=rid:m send max-width=640;max-height=360`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rid {
+    pub id: String,
+    pub direction: StreamDirection,
+    /// The payload types the stream may use, from the `pt=` restriction; empty if it may use any.
+    pub payload_types: Vec<i32>,
+    /// The other restrictions, e.g. `max-width`.
+    pub restrictions: FmtpParameters,
+}
+
+impl Rid {
+    /// Parses the value of an `a=rid` attribute.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut tokens = value.splitn(3, ' ');
+        let id = tokens.next().filter(|id| !id.is_empty())?.to_owned();
+        let direction = StreamDirection::parse(tokens.next()?)?;
+        let mut restrictions = tokens.next().unwrap_or("").parse::<FmtpParameters>().unwrap();
+        let payload_types = match restrictions.get("pt") {
+            Some(pts) => pts.split(',').map(|pt| pt.trim().parse().ok()).collect::<Option<_>>()?,
+            None => Vec::new(),
+        };
+        restrictions.remove("pt");
+        Some(Self { id, direction, payload_types, restrictions })
+    }
+
+    /// The RID which accepts this one in an answer, i.e. with the same restrictions in the opposite direction.
+    pub fn answer(&self) -> Self {
+        Self { direction: self.direction.reverse(), ..self.clone() }
+    }
+}
+
+impl fmt::Display for Rid {
+    /// Writes the value of the `a=rid` attribute for this RID.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.id, self.direction)?;
+        let mut restrictions = self.restrictions.clone();
+        if !self.payload_types.is_empty() {
+            let pts = self.payload_types.iter().map(|pt| pt.to_string()).collect::<Vec<_>>().join(",");
+            restrictions = format!("pt={};{}", pts, restrictions).parse().unwrap();
+        }
+        if !restrictions.is_empty() {
+            write!(f, " {}", restrictions)?;
+        }
+        Ok(())
+    }
+}
+
+/// One of the alternative RIDs for a simulcast stream, which may start off paused, e.g. the `~l` in
+/// `a=simulcast:send h;m;~l`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimulcastAlternative {
+    pub rid: String,
+    pub paused: bool,
+}
+
+impl SimulcastAlternative {
+    /// An alternative which isn't paused.
+    pub fn new(rid: &str) -> Self {
+        Self { rid: rid.to_owned(), paused: false }
+    }
+}
+
+/// A simulcast description, e.g. `a=simulcast:send h;m;~l`. Each direction has a list of streams, and each stream has
+/// a list of alternative RIDs, in order of preference.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Simulcast {
+    pub send: Vec<Vec<SimulcastAlternative>>,
+    pub recv: Vec<Vec<SimulcastAlternative>>,
+}
+
+impl Simulcast {
+    /// Parses the value of an `a=simulcast` attribute.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut result = Self::default();
+        let mut tokens = value.split_whitespace();
+        while let Some(direction) = tokens.next() {
+            let streams = tokens
+                .next()?
+                .split(';')
+                .map(|stream| {
+                    stream
+                        .split(',')
+                        .map(|alt| match alt.strip_prefix('~') {
+                            Some(rid) => SimulcastAlternative { rid: rid.to_owned(), paused: true },
+                            None => SimulcastAlternative::new(alt),
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            if streams.iter().flatten().any(|alt| alt.rid.is_empty()) {
+                return None;
+            }
+            match StreamDirection::parse(direction)? {
+                StreamDirection::Send => result.send = streams,
+                StreamDirection::Recv => result.recv = streams,
+            }
+        }
+        if result.send.is_empty() && result.recv.is_empty() { None } else { Some(result) }
+    }
+
+    /// The simulcast description which accepts this one in an answer, i.e. with the directions swapped.
+    pub fn answer(&self) -> Self {
+        Self { send: self.recv.clone(), recv: self.send.clone() }
+    }
+
+    /// The RIDs of every alternative in the given direction, in order.
+    pub fn rids(&self, direction: StreamDirection) -> impl Iterator<Item = &str> {
+        let streams = match direction {
+            StreamDirection::Send => &self.send,
+            StreamDirection::Recv => &self.recv,
+        };
+        streams.iter().flatten().map(|alt| alt.rid.as_str())
+    }
+
+    /// Removes the alternatives with the given RIDs, along with any streams left without alternatives.
+    pub fn remove_rids(&mut self, rids: &[String]) {
+        for streams in [&mut self.send, &mut self.recv].iter_mut() {
+            for stream in streams.iter_mut() {
+                stream.retain(|alt| !rids.contains(&alt.rid));
+            }
+            streams.retain(|stream| !stream.is_empty());
+        }
+    }
+}
+
+impl fmt::Display for Simulcast {
+    /// Writes the value of the `a=simulcast` attribute for this description.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let directions = [(StreamDirection::Send, &self.send), (StreamDirection::Recv, &self.recv)];
+        let mut first = true;
+        for (direction, streams) in directions.iter().filter(|(_, streams)| !streams.is_empty()) {
+            let streams = streams
+                .iter()
+                .map(|stream| {
+                    let alts = stream.iter().map(|alt| format!("{}{}", if alt.paused { "~" } else { "" }, alt.rid));
+                    alts.collect::<Vec<_>>().join(",")
+                })
+                .collect::<Vec<_>>()
+                .join(";");
+            write!(f, "{}{} {}", if first { "" } else { " " }, direction, streams)?;
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+/// A group of SSRCs, e.g. `a=ssrc-group:SIM 1001 1002 1003` for legacy simulcast, or `a=ssrc-group:FID 2001 2002` for
+/// a stream and its retransmissions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SsrcGroup {
+    pub semantics: String,
+    pub ssrcs: Vec<u32>,
+}
+
+impl SsrcGroup {
+    /// Parses the value of an `a=ssrc-group` attribute.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut tokens = value.split_whitespace();
+        let semantics = tokens.next()?.to_owned();
+        let ssrcs = tokens.map(|ssrc| ssrc.parse().ok()).collect::<Option<Vec<_>>>()?;
+        Some(Self { semantics, ssrcs })
+    }
+}
+
+impl fmt::Display for SsrcGroup {
+    /// Writes the value of the `a=ssrc-group` attribute for this group.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.semantics)?;
+        for ssrc in &self.ssrcs {
+            write!(f, " {}", ssrc)?;
+        }
+        Ok(())
+    }
+}
+
+impl MLine {
+    /// The RIDs described for this media, in order.
+    pub fn rids(&self) -> impl Iterator<Item = Rid> + '_ {
+        self.attributes().filter(|a| a.name() == "rid").filter_map(|a| a.value().and_then(Rid::parse))
+    }
+
+    /// The simulcast description for this media, if it has one.
+    pub fn simulcast(&self) -> Option<Simulcast> {
+        self.attribute("simulcast").and_then(|a| a.value()).and_then(Simulcast::parse)
+    }
+
+    /// The SSRC groups for this media, in order.
+    pub fn ssrc_groups(&self) -> impl Iterator<Item = SsrcGroup> + '_ {
+        self.attributes().filter(|a| a.name() == "ssrc-group").filter_map(|a| a.value().and_then(SsrcGroup::parse))
+    }
+
+    /// The SSRCs of the legacy simulcast (`a=ssrc-group:SIM`) streams for this media, lowest quality first.
+    pub fn simulcast_ssrcs(&self) -> Option<Vec<u32>> {
+        self.ssrc_groups().find(|g| g.semantics == "SIM").map(|g| g.ssrcs)
+    }
+
+    /// Adds an `a=rid` attribute to this media.
+    pub fn add_rid(&mut self, rid: &Rid) {
+        self.add_attribute("rid", Some(&rid.to_string()));
+    }
+
+    /// Replaces the `a=simulcast` attribute for this media.
+    pub fn set_simulcast(&mut self, simulcast: &Simulcast) {
+        self.retain_attributes(|a| a.name() != "simulcast");
+        self.add_attribute("simulcast", Some(&simulcast.to_string()));
+    }
+
+    /// Removes the `a=rid` attributes with the given RIDs, along with their alternatives in the `a=simulcast`
+    /// attribute, which is removed entirely if that leaves it without any streams.
+    pub fn remove_rids(&mut self, rids: &[String]) {
+        if rids.is_empty() {
+            return;
+        }
+        self.retain_attributes(|a| a.name() != "rid" || !matches!(a.value().and_then(Rid::parse), Some(rid) if rids.contains(&rid.id)));
+        if let Some(mut simulcast) = self.simulcast() {
+            simulcast.remove_rids(rids);
+            if simulcast.send.is_empty() && simulcast.recv.is_empty() {
+                self.retain_attributes(|a| a.name() != "simulcast");
+            } else {
+                self.set_simulcast(&simulcast);
+            }
+        }
+    }
+
+    /// Adds the `a=rid` and `a=simulcast` attributes which accept the simulcast described in the offered media.
+    /// Does nothing if the offered media doesn't use RID-based simulcast. The payload types each RID is restricted to
+    /// are narrowed down to the ones in this media; RIDs restricted only to payload types this media doesn't have are
+    /// left out of the answer, as RFC 8851 requires, along with their simulcast alternatives.
+    pub fn answer_simulcast(&mut self, offer: &MLine) {
+        if let Some(simulcast) = offer.simulcast() {
+            self.retain_attributes(|a| a.name() != "rid");
+            let payload_types = self.payload_types().collect::<Vec<_>>();
+            let mut rejected = Vec::new();
+            for rid in offer.rids() {
+                let mut rid = rid.answer();
+                if !rid.payload_types.is_empty() {
+                    rid.payload_types.retain(|pt| payload_types.contains(pt));
+                    if rid.payload_types.is_empty() {
+                        rejected.push(rid.id);
+                        continue;
+                    }
+                }
+                self.add_rid(&rid);
+            }
+            self.set_simulcast(&simulcast.answer());
+            self.remove_rids(&rejected);
+        }
+    }
+}
+
+impl Sdp {
+    /// Adds the `a=rid` and `a=simulcast` attributes which accept the simulcast described in the offer to this answer.
+    /// The media in the answer must be in the same order as in the offer.
+    pub fn answer_simulcast(&mut self, offer: &Sdp) {
+        for (answer, offer) in self.mlines_mut().zip(offer.mlines()) {
+            answer.answer_simulcast(offer);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "rust-sdp"))]
+mod tests {
+
+    use super::*;
+    use std::ffi::CString;
+
+    fn parse(sdp: &str) -> Sdp {
+        Sdp::parse(&CString::new(sdp).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn parse_simulcast_attributes() {
+        let rid = Rid::parse("m send pt=96,98;max-width=640;max-height=360").unwrap();
+        assert_eq!((rid.id.as_str(), rid.direction, &rid.payload_types[..]), ("m", StreamDirection::Send, &[96, 98][..]));
+        assert_eq!(rid.restrictions.get("max-width"), Some("640"));
+        assert_eq!(rid.to_string(), "m send pt=96,98;max-width=640;max-height=360");
+        assert_eq!(rid.answer().to_string(), "m recv pt=96,98;max-width=640;max-height=360");
+        assert_eq!(Rid::parse("h sideways"), None);
+
+        let simulcast = Simulcast::parse("send h;m,~m2;~l recv x").unwrap();
+        assert_eq!(simulcast.send.len(), 3);
+        assert_eq!(simulcast.send[1], vec![SimulcastAlternative::new("m"), SimulcastAlternative { rid: "m2".into(), paused: true }]);
+        assert_eq!(simulcast.rids(StreamDirection::Recv).collect::<Vec<_>>(), vec!["x"]);
+        assert_eq!(simulcast.to_string(), "send h;m,~m2;~l recv x");
+        assert_eq!(simulcast.answer().to_string(), "send x recv h;m,~m2;~l");
+        assert_eq!(Simulcast::parse("send"), None);
+        assert_eq!(Simulcast::parse("send h;;l"), None);
+
+        let group = SsrcGroup::parse("SIM 1001 1002 1003").unwrap();
+        assert_eq!(group.ssrcs, vec![1001, 1002, 1003]);
+        assert_eq!(group.to_string(), "SIM 1001 1002 1003");
+    }
+
+    #[test]
+    fn answer_simulcast_offer() {
+        let offer = parse(include_str!("testdata/simulcast.sdp"));
+        let video = offer.mlines().find(|m| m.simulcast().is_some()).unwrap();
+        assert_eq!(video.rids().map(|r| r.id).collect::<Vec<_>>(), vec!["h", "m", "l"]);
+        assert!(video.simulcast().unwrap().send[2][0].paused);
+
+        let mut answer = parse(include_str!("testdata/simulcast.sdp"));
+        for mline in answer.mlines_mut() {
+            mline.retain_attributes(|a| a.name() != "rid" && a.name() != "simulcast");
+        }
+        answer.answer_simulcast(&offer);
+        let video = answer.mlines().find(|m| m.simulcast().is_some()).unwrap();
+        assert!(video.rids().all(|r| r.direction == StreamDirection::Recv));
+        assert_eq!(video.attribute("simulcast").and_then(|a| a.value()), Some("recv h;m;~l"));
+
+        let chrome = parse(include_str!("testdata/chrome.sdp"));
+        let video = chrome.mlines().nth(1).unwrap();
+        assert_eq!(video.ssrc_groups().next(), Some(SsrcGroup { semantics: "FID".into(), ssrcs: vec![2001, 2002] }));
+        assert_eq!(video.simulcast_ssrcs(), None);
+    }
+
+    #[test]
+    fn answer_rid_payload_types() {
+        let mut offer = parse(include_str!("testdata/simulcast.sdp"));
+        let video = offer.mlines_mut().find(|m| m.simulcast().is_some()).unwrap();
+        video.retain_attributes(|a| a.name() != "rid");
+        video.add_rid(&Rid::parse("h send pt=96,98").unwrap());
+        video.add_rid(&Rid::parse("m send pt=98;max-width=640").unwrap());
+        video.add_rid(&Rid::parse("l send max-width=320").unwrap());
+
+        let mut answer = parse(include_str!("testdata/simulcast.sdp"));
+        let video = answer.mlines_mut().find(|m| m.simulcast().is_some()).unwrap();
+        assert_eq!(video.payload_types().collect::<Vec<_>>(), vec![96, 97]);
+        answer.answer_simulcast(&offer);
+        let video = answer.mlines().find(|m| m.simulcast().is_some()).unwrap();
+        let rids = video.rids().map(|r| r.to_string()).collect::<Vec<_>>();
+        assert_eq!(rids, vec!["h recv pt=96", "l recv max-width=320"]);
+        assert_eq!(video.attribute("simulcast").and_then(|a| a.value()), Some("recv h;~l"));
+
+        let video = offer.mlines_mut().next().unwrap();
+        video.retain_attributes(|a| a.name() != "rid");
+        for rid in &["h", "m", "l"] {
+            video.add_rid(&Rid::parse(&format!("{} send pt=98", rid)).unwrap());
+        }
+        answer.answer_simulcast(&offer);
+        let video = answer.mlines().next().unwrap();
+        assert_eq!((video.rids().count(), video.simulcast()), (0, None));
+    }
+}
@@ -1,10 +1,17 @@
 /// Utilities to make it easier to maintain Janus session state between plugin callbacks.
+use crate::jansson::{JanssonValue, RawJanssonValue};
 use crate::PluginSession;
+use crate::refcount::RefGuard;
+use serde::ser::Serialize;
+use std::collections::HashMap;
 use std::error::Error;
 use std::hash::{Hash, Hasher};
 use std::fmt;
 use std::ops::Deref;
-use std::sync::Arc;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
 /// An error indicating that someone handed us a null plugin session handle.
 #[derive(Debug, Clone, Copy)]
@@ -18,12 +25,45 @@ impl fmt::Display for NullHandleError {
     }
 }
 
+/// Tracks when a session last saw any activity, so that idle sessions can be found.
+#[derive(Debug)]
+struct Activity {
+    created: Instant,
+    last_active_millis: AtomicU64, // since `created`
+}
+
+impl Activity {
+    fn new() -> Self {
+        Self { created: Instant::now(), last_active_millis: AtomicU64::new(0) }
+    }
+
+    fn touch(&self) {
+        let elapsed = self.created.elapsed().as_millis() as u64;
+        self.last_active_millis.fetch_max(elapsed, Ordering::Relaxed);
+    }
+
+    fn idle_for(&self) -> Duration {
+        let last_active = Duration::from_millis(self.last_active_millis.load(Ordering::Relaxed));
+        self.created.elapsed().checked_sub(last_active).unwrap_or_default()
+    }
+}
+
+impl Clone for Activity {
+    fn clone(&self) -> Self {
+        let last_active_millis = AtomicU64::new(self.last_active_millis.load(Ordering::Relaxed));
+        Self { created: self.created, last_active_millis }
+    }
+}
+
 /// A wrapper for a Janus session. Contains a pointer to the Janus `PluginSession` (which is used to identify
 /// this session in the Janus FFI) and any Rust state associated with the session.
 #[derive(Debug, Clone)]
 pub struct SessionWrapper<T> {
     pub handle: *mut PluginSession,
     state: T,
+    activity: Activity,
+    // declared last so that the state is dropped before the handle reference is released
+    _refcount: RefGuard,
 }
 
 impl<T> Hash for SessionWrapper<T> {
@@ -46,7 +86,10 @@ impl<T> SessionWrapper<T> {
     pub unsafe fn associate(handle: *mut PluginSession, state: T) -> Result<Box<Arc<Self>>, NullHandleError> {
         match handle.as_mut() {
             Some(x) => {
-                let mut result = Box::new(Arc::new(Self { handle, state }));
+                // the Janus core assumes that we will store the handle reference, and increments it on our behalf.
+                // we're only responsible for decrementing it when we are done with it.
+                let refcount = RefGuard::adopt(&x.ref_).unwrap();
+                let mut result = Box::new(Arc::new(Self { handle, state, activity: Activity::new(), _refcount: refcount }));
                 x.plugin_handle = result.as_mut() as *mut Arc<Self> as *mut _;
                 Ok(result)
             }
@@ -68,6 +111,35 @@ impl<T> SessionWrapper<T> {
     pub fn as_ptr(&self) -> *mut PluginSession {
         self.handle
     }
+
+    /// Records that this session just saw some activity, resetting its idle time. Plugins should call this
+    /// from callbacks like `handle_message`, `incoming_rtp` and `incoming_data`.
+    pub fn touch(&self) {
+        self.activity.touch()
+    }
+
+    /// Returns how long it has been since this session last saw any activity.
+    pub fn idle_for(&self) -> Duration {
+        self.activity.idle_for()
+    }
+
+    /// Drops the reference to this session which its Janus handle holds, i.e. the box returned by `associate`, if the
+    /// handle still points to it. Returns whether it did.
+    ///
+    /// The box must not be owned by anything else, e.g. because the plugin never released it in `destroy_session`.
+    pub(crate) unsafe fn release_handle(self: &Arc<Self>) -> bool {
+        let handle = match self.handle.as_mut() {
+            Some(handle) => handle,
+            None => return false,
+        };
+        let boxed = handle.plugin_handle as *mut Arc<Self>;
+        if boxed.is_null() || !Arc::ptr_eq(&*boxed, self) {
+            return false;
+        }
+        handle.plugin_handle = ptr::null_mut();
+        drop(Box::from_raw(boxed));
+        true
+    }
 }
 
 impl<T> Deref for SessionWrapper<T> {
@@ -77,44 +149,194 @@ impl<T> Deref for SessionWrapper<T> {
     }
 }
 
-impl<T> Drop for SessionWrapper<T> {
-    fn drop(&mut self) {
-        unsafe {
-            // the Janus core assumes that we will store the handle reference, and increments it on our behalf.
-            // we're only responsible for decrementing it when we are done with it.
-            let refcount = &(*self.handle).ref_;
-            super::refcount::decrease(refcount);
+/// Session state which can describe itself in response to the Janus admin API's `query_session` request.
+pub trait QuerySession {
+    /// A serializable description of the session, which should serialize to a JSON object.
+    type Snapshot: Serialize;
+
+    /// Takes a snapshot of the current session state.
+    fn snapshot(&self) -> Self::Snapshot;
+}
+
+/// Responds to a Janus `query_session` call for a session whose state is a `T`, by serializing a snapshot of the
+/// state. Returns null if the handle doesn't have any state associated with it. Usually called via the
+/// `query_session_callback` macro.
+///
+/// # Safety
+///
+/// The handle must be null or a Janus plugin session whose state, if any, was associated as a `T`.
+pub unsafe fn query_session<T: QuerySession>(handle: *mut PluginSession) -> *mut RawJanssonValue {
+    respond_to_query::<T, _>(handle, |session| session.snapshot())
+}
+
+/// Responds to a Janus `query_session` call by serializing whatever the given function describes the session's state
+/// as, or returns null if the handle doesn't have any state associated with it.
+pub(crate) unsafe fn respond_to_query<T, S: Serialize>(
+    handle: *mut PluginSession,
+    describe: impl FnOnce(&SessionWrapper<T>) -> S,
+) -> *mut RawJanssonValue {
+    let plugin_handle = match handle.as_ref() {
+        Some(h) => h.plugin_handle,
+        None => ptr::null_mut(),
+    };
+    if plugin_handle.is_null() {
+        return ptr::null_mut();
+    }
+    let session = SessionWrapper::<T>::from_ptr(handle).unwrap();
+    match JanssonValue::from_serde(&describe(&session)) {
+        Ok(json) => json.into_raw(),
+        Err(e) => {
+            crate::janus_err!("Error serializing session state for query_session: {}", e);
+            ptr::null_mut()
         }
     }
 }
 
+/// Defines a `query_session` plugin callback which describes sessions whose state is the given type, which must
+/// implement `QuerySession`. Pass `query_session` to `build_plugin!` to use it.
+#[macro_export]
+macro_rules! query_session_callback {
+    ($state:ty) => {
+        extern "C" fn query_session(handle: *mut $crate::PluginSession) -> *mut $crate::RawJanssonValue {
+            unsafe { $crate::session::query_session::<$state>(handle) }
+        }
+    };
+}
+
+// a registered session and the ID it was given
+type Registered<T> = (u64, Arc<SessionWrapper<T>>);
+
+/// A threadsafe collection of the sessions which a plugin currently has open, keyed by their Janus handle. Each
+/// session is also given an ID when it's registered, which can be handed out to identify it, e.g. over the admin API,
+/// instead of its handle's address. IDs count up from 1 and are never reused.
+#[derive(Debug)]
+pub struct SessionRegistry<T> {
+    sessions: RwLock<HashMap<usize, Registered<T>>>,
+    next_id: AtomicU64,
+}
+
+impl<T> SessionRegistry<T> {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self { sessions: RwLock::new(HashMap::new()), next_id: AtomicU64::new(1) }
+    }
+
+    /// Adds a session to the registry, returning any session previously registered with the same handle.
+    pub fn insert(&self, session: Arc<SessionWrapper<T>>) -> Option<Arc<SessionWrapper<T>>> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.sessions.write().unwrap().insert(session.handle as usize, (id, session)).map(|(_, session)| session)
+    }
+
+    /// Removes and returns the session with the given handle, if it's registered.
+    pub fn remove(&self, handle: *mut PluginSession) -> Option<Arc<SessionWrapper<T>>> {
+        self.sessions.write().unwrap().remove(&(handle as usize)).map(|(_, session)| session)
+    }
+
+    /// Returns the session with the given handle, if it's registered.
+    pub fn get(&self, handle: *mut PluginSession) -> Option<Arc<SessionWrapper<T>>> {
+        self.sessions.read().unwrap().get(&(handle as usize)).map(|(_, session)| Arc::clone(session))
+    }
+
+    /// Returns the ID of the session with the given handle, if it's registered.
+    pub fn id(&self, handle: *mut PluginSession) -> Option<u64> {
+        self.sessions.read().unwrap().get(&(handle as usize)).map(|&(id, _)| id)
+    }
+
+    /// Returns the session with the given ID, if it's registered.
+    pub fn get_by_id(&self, id: u64) -> Option<Arc<SessionWrapper<T>>> {
+        let sessions = self.sessions.read().unwrap();
+        sessions.values().find(|&&(i, _)| i == id).map(|(_, session)| Arc::clone(session))
+    }
+
+    /// Returns a snapshot of all the registered sessions.
+    pub fn sessions(&self) -> Vec<Arc<SessionWrapper<T>>> {
+        self.sessions.read().unwrap().values().map(|(_, session)| Arc::clone(session)).collect()
+    }
+
+    /// Returns a snapshot of all the registered sessions along with their IDs, in ID order.
+    pub fn sessions_by_id(&self) -> Vec<(u64, Arc<SessionWrapper<T>>)> {
+        let mut sessions = self.sessions.read().unwrap().values().cloned().collect::<Vec<_>>();
+        sessions.sort_by_key(|&(id, _)| id);
+        sessions
+    }
+
+    /// Removes and returns all the registered sessions.
+    pub fn clear(&self) -> Vec<Arc<SessionWrapper<T>>> {
+        self.sessions.write().unwrap().drain().map(|(_, (_, session))| session).collect()
+    }
+
+    /// Returns the number of registered sessions.
+    pub fn len(&self) -> usize {
+        self.sessions.read().unwrap().len()
+    }
+
+    /// Returns whether no sessions are registered.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Default for SessionRegistry<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 // the pointer is opaque to Janus code, so this handle is threadsafe to the extent that the state is
 
 unsafe impl<T: Sync> Sync for SessionWrapper<T> {}
 unsafe impl<T: Send> Send for SessionWrapper<T> {}
 
-// todo: port to refcount branch
-/*
+/// A Janus handle with no session associated yet, for tests. Its reference count starts at one and frees nothing.
+#[cfg(test)]
+pub(crate) fn test_handle() -> PluginSession {
+    use crate::refcount::ReferenceCount;
+    extern "C" fn noop_free(_obj: *const ReferenceCount) {}
+    PluginSession {
+        gateway_handle: ptr::null_mut(),
+        plugin_handle: ptr::null_mut(),
+        stopped: 0,
+        ref_: ReferenceCount { count: 1, free: noop_free },
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
-    use std::ptr;
 
     #[test]
     fn handle_round_trip() {
         struct State(i32);
-        let mut handle = PluginSession {
-            gateway_handle: ptr::null_mut(),
-            plugin_handle: ptr::null_mut(),
-            stopped_bitfield: 0,
-            __padding: Default::default(),
-        };
+        let mut handle = test_handle();
 
         let ptr = &mut handle as *mut _;
         let session = unsafe { SessionWrapper::associate(ptr, State(42)).unwrap() };
         assert_eq!(session.as_ref() as *const _ as *mut _, handle.plugin_handle);
         assert_eq!(unsafe { SessionWrapper::<State>::from_ptr(ptr).unwrap().state.0 }, 42);
+        drop(session);
+        assert_eq!(handle.ref_.count, 0);
+    }
+
+    #[test]
+    fn query_session_snapshots() {
+        #[derive(serde::Serialize)]
+        struct Snapshot {
+            room: u32,
+        }
+        struct State(u32);
+        impl QuerySession for State {
+            type Snapshot = Snapshot;
+            fn snapshot(&self) -> Snapshot {
+                Snapshot { room: self.0 }
+            }
+        }
+        query_session_callback!(State);
+
+        let mut handle = test_handle();
+        assert!(query_session(&mut handle).is_null());
+        let _session = unsafe { SessionWrapper::associate(&mut handle, State(1234)).unwrap() };
+        let json = unsafe { JanssonValue::from_raw(query_session(&mut handle)).unwrap() };
+        assert_eq!(json.to_serde::<serde_json::Value>().unwrap(), serde_json::json!({ "room": 1234 }));
     }
 }
-*/
@@ -0,0 +1,231 @@
+/// Utilities to coordinate stopping everything a plugin started when Janus calls `destroy`.
+use crate::idle::IdleReaper;
+use crate::metrics::PrometheusServer;
+use crate::scheduler::Scheduler;
+use crate::session::SessionRegistry;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+type StopFn = Box<dyn FnOnce() + Send>;
+
+/// Collects the things a plugin needs to stop or release before `destroy` returns (background threads, schedulers,
+/// session registries, queues, ...) and stops them all, within a time limit, at shutdown. Things are stopped in the
+/// reverse of the order they were registered in, so register long-lived infrastructure first.
+#[derive(Default)]
+pub struct ShutdownCoordinator {
+    stops: Mutex<Vec<(String, StopFn)>>,
+}
+
+impl ShutdownCoordinator {
+    /// Creates a coordinator with nothing registered. Usable to initialize a `static`.
+    pub const fn new() -> Self {
+        Self { stops: Mutex::new(Vec::new()) }
+    }
+
+    /// Registers a function which stops something, and which should return once it has stopped.
+    pub fn register(&self, name: impl Into<String>, stop: impl FnOnce() + Send + 'static) {
+        self.stops.lock().unwrap().push((name.into(), Box::new(stop)));
+    }
+
+    /// Registers a thread which should be joined at shutdown. Whatever makes the thread exit, e.g. closing a queue it
+    /// reads from, should be registered after it, so that it happens first.
+    pub fn register_thread<T: Send + 'static>(&self, name: impl Into<String>, thread: JoinHandle<T>) {
+        let name = name.into();
+        let thread_name = name.clone();
+        self.register(name, move || {
+            if thread.join().is_err() {
+                crate::janus_err!("Thread {} panicked before shutdown.", thread_name);
+            }
+        });
+    }
+
+    /// Registers a scheduler which should be shut down at shutdown, cancelling all its tasks.
+    pub fn register_scheduler(&self, name: impl Into<String>, scheduler: Arc<Scheduler>) {
+        self.register(name, move || scheduler.shutdown());
+    }
+
+    /// Registers an idle session reaper which should be stopped at shutdown.
+    pub fn register_idle_reaper(&self, name: impl Into<String>, reaper: IdleReaper) {
+        self.register(name, move || reaper.stop());
+    }
+
+    /// Registers a Prometheus metrics server which should be stopped at shutdown.
+    pub fn register_prometheus_server(&self, name: impl Into<String>, server: PrometheusServer) {
+        self.register(name, move || server.stop());
+    }
+
+    /// Registers a session registry whose sessions should all be released at shutdown. Sessions still in the registry
+    /// are removed from it, and any which are still associated with their Janus handle are detached from it, dropping
+    /// the box which `SessionWrapper::associate` returned and the plugin handed to Janus. Sessions which are still
+    /// referenced from anywhere else afterwards are logged as leaked.
+    pub fn register_sessions<T: Send + Sync + 'static>(&self, name: impl Into<String>, registry: Arc<SessionRegistry<T>>) {
+        let name = name.into();
+        let registry_name = name.clone();
+        self.register(name, move || {
+            let sessions = registry.clear();
+            for session in &sessions {
+                unsafe { session.release_handle() };
+            }
+            let leaked = sessions.iter().filter(|s| Arc::strong_count(s) > 1).count();
+            if leaked > 0 {
+                crate::janus_warn!("{} sessions in {} are still referenced elsewhere at shutdown.", leaked, registry_name);
+            }
+            drop(sessions);
+        });
+    }
+
+    /// Stops everything registered, in reverse registration order, waiting up to the given time for all of it. Anything
+    /// which doesn't stop in time, or panics while stopping, is logged and abandoned, so once the time is up, anything
+    /// left is still told to stop, but not waited for. Returns the names of everything which failed to stop.
+    pub fn shutdown(&self, timeout: Duration) -> Vec<String> {
+        let deadline = Instant::now().checked_add(timeout);
+        let stops = std::mem::take(&mut *self.stops.lock().unwrap());
+        let mut failures = Vec::new();
+        for (name, stop) in stops.into_iter().rev() {
+            let (tx, rx) = mpsc::channel();
+            let spawned = thread::Builder::new().name(format!("stop {}", name)).spawn(move || {
+                stop();
+                let _ = tx.send(());
+            });
+            if let Err(e) = spawned {
+                crate::janus_err!("Couldn't start a thread to stop {}: {}", name, e);
+                failures.push(name);
+                continue;
+            }
+            let result = match deadline {
+                Some(deadline) => rx.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+                None => rx.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+            };
+            match result {
+                Ok(()) => crate::janus_verb!("Stopped {}.", name),
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    crate::janus_err!("{} didn't stop within the shutdown timeout.", name);
+                    failures.push(name);
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    crate::janus_err!("{} panicked while stopping.", name);
+                    failures.push(name);
+                }
+            }
+        }
+        failures
+    }
+}
+
+impl std::fmt::Debug for ShutdownCoordinator {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let stops = self.stops.lock().unwrap();
+        f.debug_list().entries(stops.iter().map(|(name, _)| name)).finish()
+    }
+}
+
+/// Defines a `destroy` plugin callback which shuts down everything registered with the given `ShutdownCoordinator`,
+/// waiting at most the given `Duration` for all of it to stop. Pass `destroy` to `build_plugin!` to use it.
+#[macro_export]
+macro_rules! destroy_callback {
+    ($coordinator:expr, $timeout:expr) => {
+        extern "C" fn destroy() {
+            let failures = $coordinator.shutdown($timeout);
+            if !failures.is_empty() {
+                $crate::janus_warn!("Plugin destroyed without stopping: {}", failures.join(", "));
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::idle::IdleTimeout;
+    use crate::metrics::MetricsRegistry;
+    use crate::session::{test_handle, SessionWrapper};
+
+    #[test]
+    fn stop_in_reverse_order() {
+        let coordinator = ShutdownCoordinator::new();
+        let (tx, rx) = mpsc::channel();
+        for i in 0..3 {
+            let tx = tx.clone();
+            coordinator.register(format!("task {}", i), move || tx.send(i).unwrap());
+        }
+        let worker = thread::spawn(|| 42);
+        coordinator.register_thread("worker", worker);
+        assert!(coordinator.shutdown(Duration::from_secs(5)).is_empty());
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![2, 1, 0]);
+        assert!(coordinator.shutdown(Duration::from_secs(5)).is_empty());
+    }
+
+    #[test]
+    fn report_failures() {
+        static COORDINATOR: ShutdownCoordinator = ShutdownCoordinator::new();
+        let (release, hang) = mpsc::channel::<()>();
+        COORDINATOR.register("hangs", move || { let _ = hang.recv(); });
+        COORDINATOR.register("panics", || panic!("oops"));
+        COORDINATOR.register("fine", || {});
+        destroy_callback!(COORDINATOR, Duration::from_millis(100));
+        let failures = COORDINATOR.shutdown(Duration::from_millis(100));
+        assert_eq!(failures, vec!["panics".to_owned(), "hangs".to_owned()]);
+        release.send(()).unwrap();
+        destroy(); // nothing left to stop
+    }
+
+    #[test]
+    fn share_one_timeout() {
+        let coordinator = ShutdownCoordinator::new();
+        let (release, hang) = mpsc::channel::<()>();
+        let hang = Arc::new(Mutex::new(hang));
+        for i in 0..5 {
+            let hang = Arc::clone(&hang);
+            coordinator.register(format!("hangs {}", i), move || { let _ = hang.lock().unwrap().recv(); });
+        }
+        let start = Instant::now();
+        assert_eq!(coordinator.shutdown(Duration::from_millis(200)).len(), 5);
+        assert!(start.elapsed() < Duration::from_millis(500));
+        drop(release);
+    }
+
+    #[test]
+    fn release_sessions() {
+        let registry = Arc::new(SessionRegistry::new());
+        let mut attached = test_handle();
+        let mut leaked = test_handle();
+        for handle in [&mut attached, &mut leaked].iter_mut() {
+            let session = unsafe { SessionWrapper::associate(&mut **handle, ()).unwrap() };
+            registry.insert(Arc::clone(&session));
+            let _ = Box::into_raw(session); // owned by the handle, as in a plugin
+        }
+        let elsewhere = registry.get(&mut leaked).unwrap();
+
+        let coordinator = ShutdownCoordinator::new();
+        coordinator.register_sessions("sessions", Arc::clone(&registry));
+        assert!(coordinator.shutdown(Duration::from_secs(5)).is_empty());
+        assert!(registry.is_empty());
+        assert!(attached.plugin_handle.is_null());
+        assert_eq!(attached.ref_.count, 0);
+        assert!(leaked.plugin_handle.is_null());
+        assert_eq!(Arc::strong_count(&elsewhere), 1);
+        drop(elsewhere);
+        assert_eq!(leaked.ref_.count, 0);
+    }
+
+    #[test]
+    fn stop_infrastructure() {
+        let coordinator = ShutdownCoordinator::new();
+        let scheduler = Arc::new(Scheduler::new().unwrap());
+        let task = scheduler.schedule_repeating(Duration::from_secs(60), || {});
+        coordinator.register_scheduler("scheduler", Arc::clone(&scheduler));
+        let registry = Arc::new(SessionRegistry::<()>::new());
+        let reaper = IdleReaper::spawn(registry, IdleTimeout::new(Duration::from_secs(60)), None, |_| {}).unwrap();
+        coordinator.register_idle_reaper("idle reaper", reaper);
+        let metrics = Arc::new(MetricsRegistry::new());
+        let path = std::env::temp_dir().join(format!("janus-plugin-shutdown-{}.sock", std::process::id()));
+        coordinator.register_prometheus_server("metrics", metrics.serve_prometheus(&path).unwrap());
+
+        assert!(coordinator.shutdown(Duration::from_secs(5)).is_empty());
+        assert!(task.is_cancelled());
+        assert!(!path.exists());
+    }
+}